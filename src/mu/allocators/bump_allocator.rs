@@ -292,6 +292,10 @@ impl BumpAllocator {
         }
     }
 
+    pub fn write_slice(&mut self, slice: &[u8], offset: usize) {
+        self.mmap[offset..(offset + slice.len())].copy_from_slice(slice);
+    }
+
     pub fn image_slice(&self, off: usize, len: usize) -> Option<&[u8]> {
         if off == 0 || off > self.write_barrier {
             None
@@ -0,0 +1,639 @@
+//  SPDX-FileCopyrightText: Copyright 2022 James M. Putnam (putnamjm.design@gmail.com)
+//  SPDX-License-Identifier: MIT
+
+//! mu bytecode
+//!    Op
+//!    assemble
+//!    run
+//!
+//! compiled lambda bodies are assembled into a vector of ops kept in
+//! the bytecode cache, keyed by function until the function is
+//! collected. the ops run on a value
+//! stack, lexical references to the running function's arguments are
+//! slot indices and native calls are direct function pointers.
+//! `:if` branches are assembled inline instead of as thunks, `:let`
+//! bindings extend the frame and `:block` establishes a handler for
+//! `:return` exits. bodies that don't assemble are evaluated as
+//! lists.
+//!
+//! the op span of each located form is kept, an error raised by an
+//! op takes the location of the innermost form around it.
+use {
+    crate::{
+        core::{
+            exception::{self, Condition, Exception},
+            frame::Frame,
+            funcall::LibMuFunction,
+            mu::Mu,
+            reader::Reader,
+            step::Step,
+            trace::Trace,
+            types::{Tag, Type},
+        },
+        types::{
+            cons::{Cons, ConsIter, Core as _},
+            fixnum::Fixnum,
+            function::Function,
+            symbol::{Core as _, Symbol},
+        },
+    },
+    std::sync::Arc,
+};
+
+use futures::executor::block_on;
+
+#[derive(Copy, Clone)]
+pub enum Op {
+    Const(Tag),                        // push constant
+    Global(Tag),                       // push symbol value
+    Slot(usize),                       // push running function's argument
+    Lexical(u64, usize),               // push enclosing function's argument
+    Native(Tag, LibMuFunction, usize), // call native function on nargs
+    Call(Tag, usize),                  // apply function or symbol to nargs
+    TailCall(Tag, usize),              // apply in tail position
+    JumpNil(usize),                    // pop, jump if nil
+    Jump(usize),
+    Pop,
+    Bind(u64, usize),  // pop nargs, bind them in function's frame
+    Unbind(u64),       // restore function's frame
+    Block(Tag, usize), // establish block, exits to pc
+    EndBlock,
+    Return(Tag), // pop, exit block
+}
+
+pub struct Bytecode {
+    lambda: Tag,
+    ops: Vec<Op>,
+    spans: Vec<(usize, usize, Tag)>, // first op, last op + 1, form
+}
+
+impl Bytecode {
+    pub fn assemble(mu: &Mu, lambda: Tag) -> Option<Bytecode> {
+        let mut code = Bytecode {
+            lambda,
+            ops: Vec::new(),
+            spans: Vec::new(),
+        };
+
+        if code.body(mu, Function::form(mu, lambda), true) {
+            Some(code)
+        } else {
+            None
+        }
+    }
+
+    pub fn cache(mu: &Mu, lambda: Tag) {
+        let mut cache_ref = block_on(mu.bytecode.write());
+
+        match Self::assemble(mu, lambda) {
+            Some(code) => cache_ref.insert(lambda.as_u64(), Arc::new(code)),
+            None => cache_ref.remove(&lambda.as_u64()),
+        };
+    }
+
+    pub fn code(mu: &Mu, lambda: Tag) -> Option<Arc<Bytecode>> {
+        let cache_ref = block_on(mu.bytecode.read());
+
+        cache_ref.get(&lambda.as_u64()).cloned()
+    }
+
+    // forget the code of unmarked functions, their images are reused
+    pub fn gc(mu: &Mu) {
+        let heap_ref = block_on(mu.heap.read());
+        let mut cache_ref = block_on(mu.bytecode.write());
+
+        cache_ref.retain(|func, _| match Tag::from_u64(*func) {
+            Tag::Indirect(indirect) => heap_ref
+                .get_image_refbit(indirect.image_id() as usize)
+                .unwrap_or(false),
+            Tag::Direct(_) => false,
+        })
+    }
+
+    fn body(&mut self, mu: &Mu, body: Tag, tail: bool) -> bool {
+        let forms = ConsIter::new(mu, body)
+            .map(|cons| Cons::car(mu, cons))
+            .collect::<Vec<Tag>>();
+
+        match forms.split_last() {
+            Some((last, forms)) => {
+                for form in forms {
+                    if !self.form(mu, *form, false) {
+                        return false;
+                    }
+
+                    self.ops.push(Op::Pop)
+                }
+
+                self.form(mu, *last, tail)
+            }
+            None => {
+                self.ops.push(Op::Const(Tag::nil()));
+                true
+            }
+        }
+    }
+
+    fn args(&mut self, mu: &Mu, args: Tag) -> Option<usize> {
+        let mut nargs = 0;
+
+        for cons in ConsIter::new(mu, args) {
+            if !self.form(mu, Cons::car(mu, cons), false) {
+                return None;
+            }
+
+            nargs += 1
+        }
+
+        Some(nargs)
+    }
+
+    // a branch of a compiled :if, a template of no arguments
+    fn is_thunk(mu: &Mu, func: Tag) -> bool {
+        func.type_of() == Type::Function
+            && Function::arity(mu, func).eq_(&Fixnum::as_tag(0))
+            && matches!(Function::form(mu, func).type_of(), Type::Cons | Type::Null)
+    }
+
+    fn form(&mut self, mu: &Mu, form: Tag, tail: bool) -> bool {
+        let start = self.ops.len();
+
+        if !self.form_(mu, form, tail) {
+            return false;
+        }
+
+        if Reader::location(mu, form).is_some() {
+            self.spans.push((start, self.ops.len(), form))
+        }
+
+        true
+    }
+
+    fn form_(&mut self, mu: &Mu, form: Tag, tail: bool) -> bool {
+        match form.type_of() {
+            Type::Symbol => {
+                self.ops.push(Op::Global(form));
+                true
+            }
+            Type::Cons => {
+                let func = Cons::car(mu, form);
+                let args = Cons::cdr(mu, form);
+
+                match func.type_of() {
+                    Type::Keyword if func.eq_(&Symbol::keyword("quote")) => {
+                        self.ops.push(Op::Const(Cons::car(mu, args)));
+                        true
+                    }
+                    Type::Keyword if func.eq_(&Symbol::keyword("progn")) => {
+                        self.body(mu, args, tail)
+                    }
+                    Type::Keyword if func.eq_(&Symbol::keyword("let")) => {
+                        let id = Cons::car(mu, args);
+
+                        if id.type_of() != Type::Fixnum {
+                            return false;
+                        }
+
+                        let id = Fixnum::as_i64(id) as u64;
+                        let nargs =
+                            match self.args(mu, Cons::nth(mu, 1, args).unwrap_or(Tag::nil())) {
+                                Some(nargs) => nargs,
+                                None => return false,
+                            };
+
+                        self.ops.push(Op::Bind(id, nargs));
+
+                        // a tail call releases our own frame, not others
+                        let tail = tail && id == self.lambda.as_u64();
+
+                        if !self.body(mu, Cons::cdr(mu, Cons::cdr(mu, args)), tail) {
+                            return false;
+                        }
+
+                        self.ops.push(Op::Unbind(id));
+                        true
+                    }
+                    Type::Keyword if func.eq_(&Symbol::keyword("block")) => {
+                        let block = self.ops.len();
+
+                        self.ops.push(Op::Block(Cons::car(mu, args), 0));
+
+                        if !self.body(mu, Cons::cdr(mu, args), false) {
+                            return false;
+                        }
+
+                        self.ops.push(Op::EndBlock);
+                        self.ops[block] = Op::Block(Cons::car(mu, args), self.ops.len());
+                        true
+                    }
+                    Type::Keyword if func.eq_(&Symbol::keyword("return")) => {
+                        if !self.form(mu, Cons::nth(mu, 1, args).unwrap_or(Tag::nil()), false) {
+                            return false;
+                        }
+
+                        self.ops.push(Op::Return(Cons::car(mu, args)));
+                        true
+                    }
+                    Type::Function if func.eq_(&mu.if_) => {
+                        let (test, true_fn, false_fn) = match Cons::length(mu, args) {
+                            Some(3) => (
+                                Cons::nth(mu, 0, args).unwrap(),
+                                Cons::nth(mu, 1, args).unwrap(),
+                                Cons::nth(mu, 2, args).unwrap(),
+                            ),
+                            _ => return false,
+                        };
+
+                        if !Self::is_thunk(mu, true_fn) || !Self::is_thunk(mu, false_fn) {
+                            return self.call(mu, func, args, tail);
+                        }
+
+                        if !self.form(mu, test, false) {
+                            return false;
+                        }
+
+                        let jump_nil = self.ops.len();
+                        self.ops.push(Op::JumpNil(0));
+
+                        if !self.body(mu, Function::form(mu, true_fn), tail) {
+                            return false;
+                        }
+
+                        let jump = self.ops.len();
+                        self.ops.push(Op::Jump(0));
+                        self.ops[jump_nil] = Op::JumpNil(self.ops.len());
+
+                        if !self.body(mu, Function::form(mu, false_fn), tail) {
+                            return false;
+                        }
+
+                        self.ops[jump] = Op::Jump(self.ops.len());
+                        true
+                    }
+                    Type::Function => {
+                        let fn_key = Function::form(mu, func);
+
+                        if fn_key.eq_(&Symbol::keyword("fr-ref")) {
+                            let (id, nth) = match (Cons::nth(mu, 0, args), Cons::nth(mu, 1, args)) {
+                                (Some(id), Some(nth))
+                                    if id.type_of() == Type::Fixnum
+                                        && nth.type_of() == Type::Fixnum
+                                        && Cons::length(mu, args) == Some(2) =>
+                                {
+                                    (Fixnum::as_i64(id) as u64, Fixnum::as_i64(nth) as usize)
+                                }
+                                _ => return self.call(mu, func, args, tail),
+                            };
+
+                            self.ops.push(if id == self.lambda.as_u64() {
+                                Op::Slot(nth)
+                            } else {
+                                Op::Lexical(id, nth)
+                            });
+
+                            return true;
+                        }
+
+                        match fn_key.type_of() {
+                            Type::Keyword => {
+                                let nreqs = Fixnum::as_i64(Function::arity(mu, func)) as usize;
+
+                                match mu.native_map.get(&fn_key.as_u64()) {
+                                    Some(fn_) if Cons::length(mu, args) == Some(nreqs) => {
+                                        match self.args(mu, args) {
+                                            Some(nargs) => {
+                                                self.ops.push(Op::Native(func, *fn_, nargs));
+                                                true
+                                            }
+                                            None => false,
+                                        }
+                                    }
+                                    _ => self.call(mu, func, args, tail),
+                                }
+                            }
+                            _ => self.call(mu, func, args, tail),
+                        }
+                    }
+                    Type::Symbol => self.call(mu, func, args, tail),
+                    _ => false,
+                }
+            }
+            _ => {
+                self.ops.push(Op::Const(form));
+                true
+            }
+        }
+    }
+
+    fn call(&mut self, mu: &Mu, func: Tag, args: Tag, tail: bool) -> bool {
+        match self.args(mu, args) {
+            Some(nargs) => {
+                self.ops.push(if tail {
+                    Op::TailCall(func, nargs)
+                } else {
+                    Op::Call(func, nargs)
+                });
+
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn function(mu: &Mu, func: Tag) -> exception::Result<Tag> {
+        match func.type_of() {
+            Type::Function => Ok(func),
+            Type::Symbol => {
+                if Symbol::is_unbound(mu, func) {
+                    Err(Exception::new(Condition::Unbound, "eval", func))
+                } else {
+                    let fn_ = Symbol::value(mu, func);
+
+                    match fn_.type_of() {
+                        Type::Function => Ok(fn_),
+                        _ => Err(Exception::new(Condition::Type, "eval", func)),
+                    }
+                }
+            }
+            _ => Err(Exception::new(Condition::Type, "eval", func)),
+        }
+    }
+
+    // run lambda's code in its frame, returns the value or the
+    // frame of a tail call to another lambda
+    pub fn run(mu: &Mu, lambda: Tag, code: &Bytecode) -> exception::Result<(Tag, Option<Frame>)> {
+        let mut machine = Machine {
+            lambda,
+            argv: Frame::frame_argv(mu, lambda),
+            stack: Vec::new(),
+            binds: Vec::new(),
+            blocks: Vec::new(),
+            pc: 0,
+        };
+
+        loop {
+            match machine.exec(mu, &code.ops) {
+                Ok(value) => return Ok(value),
+                Err(e) => {
+                    if !machine.catch(mu, &e) {
+                        machine.unbind(mu, 0);
+                        return Err(code.locate_error(mu, machine.pc, e));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Bytecode {
+    fn locate_error(&self, mu: &Mu, pc: usize, e: Exception) -> Exception {
+        match self
+            .spans
+            .iter()
+            .filter(|(start, end, _)| *start <= pc && pc < *end)
+            .min_by_key(|(start, end, _)| end - start)
+        {
+            Some((_, _, form)) => Reader::locate_error(mu, *form, e),
+            None => e,
+        }
+    }
+}
+
+struct Machine {
+    lambda: Tag,
+    argv: Vec<Tag>,
+    stack: Vec<Tag>,
+    binds: Vec<(u64, usize)>,                // function, frame length
+    blocks: Vec<(Tag, usize, usize, usize)>, // block, stack, binds, exit pc
+    pc: usize,
+}
+
+impl Machine {
+    // restore the frames bound since depth
+    fn unbind(&mut self, mu: &Mu, depth: usize) {
+        while self.binds.len() > depth {
+            let (id, len) = self.binds.pop().unwrap();
+
+            if id == self.lambda.as_u64() {
+                self.argv.truncate(len)
+            }
+
+            Frame::frame_stack_unbind(mu, id, len)
+        }
+    }
+
+    // resume at the exit of the block a :return is for
+    fn catch(&mut self, mu: &Mu, e: &Exception) -> bool {
+        if e.condition != Condition::Return {
+            return false;
+        }
+
+        match self
+            .blocks
+            .iter()
+            .rposition(|(block, _, _, _)| e.source.eq_(block))
+        {
+            Some(nth) => {
+                let (_, stack, binds, pc) = self.blocks[nth];
+
+                self.blocks.truncate(nth);
+                self.unbind(mu, binds);
+                self.stack.truncate(stack);
+                self.stack.push(e.object);
+                self.pc = pc;
+
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn exec(&mut self, mu: &Mu, ops: &[Op]) -> exception::Result<(Tag, Option<Frame>)> {
+        let stack = &mut self.stack;
+
+        while self.pc < ops.len() {
+            match ops[self.pc] {
+                Op::Const(tag) => stack.push(tag),
+                Op::Global(symbol) => {
+                    if Symbol::is_unbound(mu, symbol) {
+                        return Err(Exception::new(Condition::Unbound, "eval", symbol));
+                    }
+
+                    stack.push(Symbol::value(mu, symbol))
+                }
+                Op::Slot(nth) => stack.push(self.argv[nth]),
+                Op::Lexical(id, nth) => match Frame::frame_ref(mu, id, nth) {
+                    Some(tag) => stack.push(tag),
+                    None => return Err(Exception::new(Condition::Range, "fr-ref", self.lambda)),
+                },
+                Op::Native(func, fn_, nargs) => {
+                    let mut fp = Frame {
+                        func,
+                        argv: stack.split_off(stack.len() - nargs),
+                        value: Tag::nil(),
+                    };
+
+                    // traced or stepped natives are applied so the hooks run
+                    if Trace::stream(mu, func).is_some() || Step::is_set(mu) {
+                        match fp.apply(mu, func) {
+                            Ok(value) => stack.push(value),
+                            Err(e) => return Err(e),
+                        }
+                    } else {
+                        match fn_(mu, &mut fp) {
+                            Ok(_) => stack.push(fp.value),
+                            Err(e) => return Err(e),
+                        }
+                    }
+                }
+                Op::Call(func, nargs) | Op::TailCall(func, nargs) => {
+                    let argv = stack.split_off(stack.len() - nargs);
+
+                    match Bytecode::function(mu, func) {
+                        Ok(func) => {
+                            let frame = Frame {
+                                func,
+                                argv,
+                                value: Tag::nil(),
+                            };
+
+                            let is_lambda = matches!(
+                                Function::form(mu, func).type_of(),
+                                Type::Cons | Type::Function
+                            );
+
+                            if is_lambda && matches!(ops[self.pc], Op::TailCall(_, _)) {
+                                return Ok((Tag::nil(), Some(frame)));
+                            }
+
+                            match frame.apply(mu, func) {
+                                Ok(value) => stack.push(value),
+                                Err(e) => return Err(e),
+                            }
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+                Op::JumpNil(target) => {
+                    if stack.pop().unwrap().null_() {
+                        self.pc = target;
+                        continue;
+                    }
+                }
+                Op::Jump(target) => {
+                    self.pc = target;
+                    continue;
+                }
+                Op::Pop => {
+                    stack.pop();
+                }
+                Op::Bind(id, nargs) => {
+                    let values = stack.split_off(stack.len() - nargs);
+
+                    match Frame::frame_stack_bind(mu, id, &values) {
+                        Some(len) => {
+                            if id == self.lambda.as_u64() {
+                                self.argv.extend_from_slice(&values)
+                            }
+
+                            self.binds.push((id, len))
+                        }
+                        None => return Err(Exception::new(Condition::Range, ":let", self.lambda)),
+                    }
+                }
+                Op::Unbind(id) => {
+                    if let Some((_, len)) = self.binds.pop() {
+                        if id == self.lambda.as_u64() {
+                            self.argv.truncate(len)
+                        }
+
+                        Frame::frame_stack_unbind(mu, id, len)
+                    }
+                }
+                Op::Block(block, pc) => {
+                    self.blocks.push((block, stack.len(), self.binds.len(), pc))
+                }
+                Op::EndBlock => {
+                    self.blocks.pop();
+                }
+                Op::Return(block) => return Err(Exception::return_(block, stack.pop().unwrap())),
+            }
+
+            self.pc += 1
+        }
+
+        Ok((stack.pop().unwrap_or(Tag::nil()), None))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::{
+        bytecode::{Bytecode, Op},
+        compiler::Compiler,
+        mu::{Core, Mu},
+        stream,
+        types::Tag,
+    };
+    use crate::types::{
+        fixnum::Fixnum, function::Function, stream::Core as _, streambuilder::StreamBuilder,
+    };
+
+    #[test]
+    fn bytecode() {
+        let config = match Mu::config("".to_string()) {
+            Some(config) => config,
+            None => return assert!(false),
+        };
+
+        let mu: &Mu = &Core::new(&config);
+        let lambda = Function::new(Fixnum::as_tag(0), Tag::nil()).evict(mu);
+
+        match Bytecode::assemble(mu, lambda) {
+            Some(code) => match code.ops[..] {
+                [Op::Const(tag)] => assert!(tag.null_()),
+                _ => assert!(false),
+            },
+            None => assert!(false),
+        }
+    }
+
+    #[test]
+    fn run() {
+        let config = match Mu::config("".to_string()) {
+            Some(config) => config,
+            None => return assert!(false),
+        };
+
+        let mu: &Mu = &Core::new(&config);
+        let stream = match StreamBuilder::new()
+            .string("(:lambda (x) (mu:fx-add x 1))".to_string())
+            .input()
+            .build(mu)
+        {
+            Ok(stream) => stream.evict(mu),
+            Err(_) => return assert!(false),
+        };
+
+        let lambda = match <Mu as stream::Core>::read(mu, stream, true, Tag::nil(), false) {
+            Ok(expr) => match Compiler::compile(mu, expr) {
+                Ok(lambda) => lambda,
+                Err(_) => return assert!(false),
+            },
+            Err(_) => return assert!(false),
+        };
+
+        assert!(Bytecode::code(mu, lambda).is_some());
+
+        match mu.apply_(lambda, vec![Fixnum::as_tag(41)]) {
+            Ok(value) => assert_eq!(Fixnum::as_i64(value), 42),
+            Err(_) => assert!(false),
+        }
+
+        // nothing refers to the lambda, its code goes with it
+        match mu.gc() {
+            Ok(_) => assert!(Bytecode::code(mu, lambda).is_none()),
+            Err(_) => assert!(false),
+        }
+    }
+}
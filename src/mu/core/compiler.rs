@@ -7,17 +7,20 @@
 use crate::{
     async_::context::{Context, Core as _},
     core::{
+        bytecode::Bytecode,
         exception::{self, Condition, Exception},
         frame::Frame,
         mu::Mu,
         namespace::Namespace,
+        reader::Reader,
         types::{Tag, Type},
     },
     types::{
         cons::{Cons, ConsIter, Core as _},
         fixnum::Fixnum,
-        function::Function,
-        symbol::{Core as _, Symbol},
+        function::{Function, LambdaList},
+        symbol::{Core as _, Symbol, UNBOUND},
+        vector::{Core as _, Vector},
     },
 };
 
@@ -30,9 +33,13 @@ type SpecMap = (Tag, SpecFn);
 lazy_static! {
     static ref SPECMAP: Vec<SpecMap> = vec![
         (Symbol::keyword("async"), Compiler::async_),
+        (Symbol::keyword("block"), Compiler::block),
         (Symbol::keyword("if"), Compiler::if_),
         (Symbol::keyword("lambda"), Compiler::lambda),
+        (Symbol::keyword("let"), Compiler::let_),
+        (Symbol::keyword("progn"), Compiler::progn),
         (Symbol::keyword("quote"), Compiler::quoted_list),
+        (Symbol::keyword("return"), Compiler::return_),
     ];
 }
 
@@ -44,39 +51,22 @@ impl Compiler {
             return Err(Exception::new(Condition::Syntax, ":if", args));
         }
 
-        let lambda = Symbol::keyword("lambda");
+        let thunk = |nth| match Cons::nth(mu, nth, args) {
+            Some(form) => Self::lambda_(mu, Cons::vlist(mu, &[Tag::nil(), form]), false, true),
+            None => panic!(),
+        };
 
-        let if_vec = vec![
-            mu.if_,
-            match Cons::nth(mu, 0, args) {
-                Some(t) => t,
-                None => panic!(),
-            },
-            Cons::vlist(
-                mu,
-                &[
-                    lambda,
-                    Tag::nil(),
-                    match Cons::nth(mu, 1, args) {
-                        Some(t) => t,
-                        None => panic!(),
-                    },
-                ],
-            ),
-            Cons::vlist(
-                mu,
-                &[
-                    lambda,
-                    Tag::nil(),
-                    match Cons::nth(mu, 2, args) {
-                        Some(t) => t,
-                        None => panic!(),
-                    },
-                ],
-            ),
-        ];
+        let test = match Cons::nth(mu, 0, args) {
+            Some(t) => Self::compile(mu, t),
+            None => panic!(),
+        };
 
-        Self::compile(mu, Cons::vlist(mu, &if_vec))
+        match (test, thunk(1), thunk(2)) {
+            (Ok(test), Ok(true_fn), Ok(false_fn)) => {
+                Ok(Cons::vlist(mu, &[mu.if_, test, true_fn, false_fn]))
+            }
+            (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => Err(e),
+        }
     }
 
     pub fn quoted_list(mu: &Mu, list: Tag) -> exception::Result<Tag> {
@@ -87,6 +77,168 @@ impl Compiler {
         Ok(Cons::new(Symbol::keyword("quote"), list).evict(mu))
     }
 
+    pub fn progn(mu: &Mu, args: Tag) -> exception::Result<Tag> {
+        match Cons::length(mu, args) {
+            Some(0) => Ok(Tag::nil()),
+            Some(1) => Self::compile(mu, Cons::car(mu, args)),
+            Some(_) => match Self::list(mu, args) {
+                Ok(body) => Ok(Cons::new(Symbol::keyword("progn"), body).evict(mu)),
+                Err(e) => Err(e),
+            },
+            None => Err(Exception::new(Condition::Syntax, ":progn", args)),
+        }
+    }
+
+    // (:let ((symbol form)...) body...) binds symbols in the frame of
+    // the innermost lambda. at top level it's a lambda application.
+    pub fn let_(mu: &Mu, args: Tag) -> exception::Result<Tag> {
+        let (bindings, body) = match args.type_of() {
+            Type::Cons => match Cons::car(mu, args).type_of() {
+                Type::Null | Type::Cons => (Cons::car(mu, args), Cons::cdr(mu, args)),
+                _ => return Err(Exception::new(Condition::Syntax, ":let", args)),
+            },
+            _ => return Err(Exception::new(Condition::Syntax, ":let", args)),
+        };
+
+        let mut symbols = Vec::new();
+        let mut forms = Vec::new();
+
+        for cons in ConsIter::new(mu, bindings) {
+            let binding = Cons::car(mu, cons);
+
+            let (symbol, form) = match binding.type_of() {
+                Type::Symbol => (binding, Tag::nil()),
+                Type::Cons if Cons::length(mu, binding) == Some(2) => {
+                    (Cons::car(mu, binding), Cons::nth(mu, 1, binding).unwrap())
+                }
+                _ => return Err(Exception::new(Condition::Syntax, ":let", binding)),
+            };
+
+            if symbol.type_of() != Type::Symbol || symbols.iter().any(|lex| symbol.eq_(lex)) {
+                return Err(Exception::new(Condition::Syntax, ":let", symbol));
+            }
+
+            symbols.push(symbol);
+            forms.push(form)
+        }
+
+        if symbols.is_empty() {
+            return Self::progn(mu, body);
+        }
+
+        // :if branches are evaluated in the frame of their lambda
+        let scope = block_on(mu.compile.read())
+            .iter()
+            .rposition(|(_, _, _, thunk)| !thunk);
+
+        let index = match scope {
+            Some(index) => index,
+            None => {
+                let lambda = Cons::new(
+                    Symbol::keyword("lambda"),
+                    Cons::new(Cons::vlist(mu, &symbols), body).evict(mu),
+                )
+                .evict(mu);
+
+                return Self::compile(mu, Cons::new(lambda, Cons::vlist(mu, &forms)).evict(mu));
+            }
+        };
+
+        // the forms are compiled outside the scope of the bindings
+        match Self::list(mu, Cons::vlist(mu, &forms)) {
+            Ok(forms) => {
+                let (func, len) = {
+                    let mut lexenv_ref = block_on(mu.compile.write());
+                    let func = lexenv_ref[index].0;
+                    let len = lexenv_ref[index].1.len();
+
+                    for (_, _, closed, _) in lexenv_ref[index + 1..].iter_mut() {
+                        if !closed.iter().any(|fn_| fn_.eq_(&func)) {
+                            closed.push(func)
+                        }
+                    }
+
+                    lexenv_ref[index].1.extend(symbols);
+                    (func, len)
+                };
+
+                let body = Self::list(mu, body);
+
+                block_on(mu.compile.write())[index].1.truncate(len);
+
+                match body {
+                    Ok(body) => Ok(Cons::new(
+                        Symbol::keyword("let"),
+                        Cons::new(
+                            Fixnum::as_tag(func.as_u64() as i64),
+                            Cons::new(forms, body).evict(mu),
+                        )
+                        .evict(mu),
+                    )
+                    .evict(mu)),
+                    Err(e) => Err(e),
+                }
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    // (:block name body...), name is compiled to an uninterned symbol
+    // unique to the block
+    pub fn block(mu: &Mu, args: Tag) -> exception::Result<Tag> {
+        let name = match args.type_of() {
+            Type::Cons => Cons::car(mu, args),
+            _ => return Err(Exception::new(Condition::Syntax, ":block", args)),
+        };
+
+        if name.type_of() != Type::Symbol {
+            return Err(Exception::new(Condition::Type, ":block", name));
+        }
+
+        let block = Symbol::new(
+            mu,
+            Tag::nil(),
+            &Vector::as_string(mu, Symbol::name(mu, name)),
+            *UNBOUND,
+        )
+        .evict(mu);
+
+        block_on(mu.blocks.write()).push((name, block));
+
+        let body = Self::list(mu, Cons::cdr(mu, args));
+
+        block_on(mu.blocks.write()).pop();
+
+        match body {
+            Ok(body) => {
+                Ok(Cons::new(Symbol::keyword("block"), Cons::new(block, body).evict(mu)).evict(mu))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    // (:return name [form])
+    pub fn return_(mu: &Mu, args: Tag) -> exception::Result<Tag> {
+        let (name, form) = match Cons::length(mu, args) {
+            Some(1) => (Cons::car(mu, args), Tag::nil()),
+            Some(2) => (Cons::car(mu, args), Cons::nth(mu, 1, args).unwrap()),
+            _ => return Err(Exception::new(Condition::Syntax, ":return", args)),
+        };
+
+        let block = match block_on(mu.blocks.read())
+            .iter()
+            .rfind(|(block_name, _)| name.eq_(block_name))
+        {
+            Some((_, block)) => *block,
+            None => return Err(Exception::new(Condition::Syntax, ":return", name)),
+        };
+
+        match Self::compile(mu, form) {
+            Ok(form) => Ok(Cons::vlist(mu, &[Symbol::keyword("return"), block, form])),
+            Err(e) => Err(e),
+        }
+    }
+
     pub fn special_form(mu: &Mu, name: Tag, args: Tag) -> exception::Result<Tag> {
         match SPECMAP.iter().copied().find(|spec| name.eq_(&spec.0)) {
             Some(spec) => spec.1(mu, args),
@@ -109,24 +261,98 @@ impl Compiler {
     }
 
     pub fn lambda(mu: &Mu, args: Tag) -> exception::Result<Tag> {
-        fn compile_frame_symbols(mu: &Mu, lambda: Tag) -> exception::Result<Vec<Tag>> {
+        Self::lambda_(mu, args, true, false)
+    }
+
+    // a lambda that references lexicals of an enclosing function
+    // compiles to a closure constructor, unless it's applied in the
+    // scope it's compiled in. thunks are the branches of an :if.
+    fn lambda_(mu: &Mu, args: Tag, close: bool, thunk: bool) -> exception::Result<Tag> {
+        // parse a lambda list into its bound symbols and descriptor,
+        // the descriptor's defaults are left uncompiled
+        //
+        // (req... [&optional opt...] [&rest rest] [&key key...])
+        //
+        // optional and keyword arguments are a symbol or (symbol default)
+        fn compile_frame_symbols(
+            mu: &Mu,
+            lambda: Tag,
+        ) -> exception::Result<(Vec<Tag>, LambdaList)> {
+            const LAMBDA_KEYS: [&str; 4] = ["", "&optional", "&rest", "&key"];
+
             let mut symvec = Vec::new();
+            let mut lambda_list = LambdaList::new(0);
+            let mut state = 0;
 
             for cons in ConsIter::new(mu, lambda) {
-                let symbol = Cons::car(mu, cons);
-                if symbol.type_of() == Type::Symbol {
-                    match symvec.iter().rev().position(|lex| symbol.eq_(lex)) {
-                        Some(_) => {
-                            return Err(Exception::new(Condition::Syntax, "lexical", symbol))
+                let elt = Cons::car(mu, cons);
+
+                let (symbol, default) = match elt.type_of() {
+                    Type::Symbol => (elt, Tag::nil()),
+                    Type::Cons
+                        if LAMBDA_KEYS[state] == "&optional" || LAMBDA_KEYS[state] == "&key" =>
+                    {
+                        match Cons::length(mu, elt) {
+                            Some(2) => (Cons::car(mu, elt), Cons::nth(mu, 1, elt).unwrap()),
+                            _ => return Err(Exception::new(Condition::Syntax, "lexical", elt)),
                         }
-                        _ => symvec.push(symbol),
                     }
-                } else {
+                    _ => return Err(Exception::new(Condition::Type, "lexical", elt)),
+                };
+
+                if symbol.type_of() != Type::Symbol {
                     return Err(Exception::new(Condition::Type, "lexical", symbol));
                 }
+
+                let name = Vector::as_string(mu, Symbol::name(mu, symbol));
+
+                if let Some(key) = LAMBDA_KEYS.iter().position(|key| *key == name) {
+                    let dangling_rest = LAMBDA_KEYS[state] == "&rest" && !lambda_list.rest;
+
+                    if elt.type_of() != Type::Symbol || key <= state || dangling_rest {
+                        return Err(Exception::new(Condition::Syntax, "lexical", symbol));
+                    }
+
+                    state = key;
+                    continue;
+                }
+
+                if symvec.iter().any(|lex| symbol.eq_(lex)) {
+                    return Err(Exception::new(Condition::Syntax, "lexical", symbol));
+                }
+
+                match LAMBDA_KEYS[state] {
+                    "&optional" => {
+                        lambda_list.nopts += 1;
+                        lambda_list.defaults.push(default)
+                    }
+                    "&rest" => {
+                        if lambda_list.rest {
+                            return Err(Exception::new(Condition::Syntax, "lexical", symbol));
+                        }
+
+                        lambda_list.rest = true
+                    }
+                    "&key" => {
+                        // keywords are immediate
+                        if name.len() > 7 {
+                            return Err(Exception::new(Condition::Syntax, "lexical", symbol));
+                        }
+
+                        lambda_list.keys.push(Symbol::keyword(&name));
+                        lambda_list.defaults.push(default)
+                    }
+                    _ => lambda_list.nreqs += 1,
+                }
+
+                symvec.push(symbol)
+            }
+
+            if LAMBDA_KEYS[state] == "&rest" && !lambda_list.rest {
+                return Err(Exception::new(Condition::Syntax, "lexical", lambda));
             }
 
-            Ok(symvec)
+            Ok((symvec, lambda_list))
         }
 
         let (lambda, body) = match args.type_of() {
@@ -141,37 +367,57 @@ impl Compiler {
             _ => return Err(Exception::new(Condition::Syntax, "lambda", args)),
         };
 
-        let func = Function::new(
-            Fixnum::as_tag(Cons::length(mu, lambda).unwrap() as i64),
-            Tag::nil(),
-        )
-        .evict(mu);
+        let func = Function::new(Fixnum::as_tag(0), Tag::nil()).evict(mu);
 
-        match compile_frame_symbols(mu, lambda) {
-            Ok(lexicals) => {
+        let mut lambda_list = match compile_frame_symbols(mu, lambda) {
+            Ok((lexicals, lambda_list)) => {
                 let mut lexenv_ref = block_on(mu.compile.write());
 
-                lexenv_ref.push((func, lexicals));
+                lexenv_ref.push((func, lexicals, Vec::new(), thunk));
+                lambda_list
             }
             Err(e) => return Err(e),
         };
 
-        let form = match Self::list(mu, body) {
-            Ok(form) => {
-                let mut function = Function::to_image(mu, func);
-                function.form = form;
-                Function::update(mu, &function, func);
+        // defaults are compiled in the function's scope
+        let form = match Self::list(mu, Cons::vlist(mu, &lambda_list.defaults)) {
+            Ok(defaults) => {
+                lambda_list.defaults = ConsIter::new(mu, defaults)
+                    .map(|cons| Cons::car(mu, cons))
+                    .collect::<Vec<Tag>>();
 
-                Ok(func)
+                Self::list(mu, body)
             }
             Err(e) => Err(e),
         };
 
-        let mut lexenv_ref = block_on(mu.compile.write());
+        // the lambda list symbols name the frame's arguments
+        let closed = match block_on(mu.compile.write()).pop() {
+            Some((_, lexicals, closed, _)) => {
+                block_on(mu.lambda_names.write()).insert(func.as_u64(), lexicals);
+                closed
+            }
+            None => panic!(),
+        };
 
-        lexenv_ref.pop();
+        match form {
+            Ok(form) => {
+                let mut function = Function::to_image(mu, func);
+                function.arity = lambda_list.arity(mu);
+                function.form = form;
 
-        form
+                function.env = Cons::vlist(mu, &closed);
+                Function::update(mu, &function, func);
+                Bytecode::cache(mu, func);
+
+                if close && !closed.is_empty() {
+                    Ok(Cons::vlist(mu, &[mu.closure_, func]))
+                } else {
+                    Ok(func)
+                }
+            }
+            Err(e) => Err(e),
+        }
     }
 
     pub fn async_(mu: &Mu, args: Tag) -> exception::Result<Tag> {
@@ -197,8 +443,8 @@ impl Compiler {
                     Err(e) => return Err(e),
                 };
 
-                let arity = Fixnum::as_i64(Function::arity(mu, fn_arg));
-                if arity != Cons::length(mu, async_args).unwrap() as i64 {
+                let lambda_list = Function::lambda_list(mu, fn_arg);
+                if !lambda_list.accepts(Cons::length(mu, async_args).unwrap()) {
                     return Err(Exception::new(Condition::Arity, "async", args));
                 }
 
@@ -213,24 +459,50 @@ impl Compiler {
         }
     }
 
-    pub fn lexical(mu: &Mu, symbol: Tag) -> exception::Result<Tag> {
-        let lexenv_ref = block_on(mu.compile.read());
-
-        for frame in lexenv_ref.iter().rev() {
-            let (tag, symbols) = frame;
+    // compile a form in function position
+    fn function(mu: &Mu, form: Tag) -> exception::Result<Tag> {
+        match form.type_of() {
+            Type::Cons if Cons::car(mu, form).eq_(&Symbol::keyword("lambda")) => {
+                Self::lambda_(mu, Cons::cdr(mu, form), false, false)
+            }
+            _ => Self::compile(mu, form),
+        }
+    }
 
-            if let Some(nth) = symbols.iter().position(|lex| symbol.eq_(lex)) {
-                let lex_ref = vec![
-                    Namespace::intern_symbol(mu, mu.mu_ns, "fr-ref".to_string(), Tag::nil()),
-                    Fixnum::as_tag(tag.as_u64() as i64),
-                    Fixnum::as_tag(nth as i64),
-                ];
+    pub fn lexical(mu: &Mu, symbol: Tag) -> exception::Result<Tag> {
+        let lexical = {
+            let mut lexenv_ref = block_on(mu.compile.write());
+
+            match lexenv_ref
+                .iter()
+                .rposition(|(_, symbols, _, _)| symbols.iter().any(|lex| symbol.eq_(lex)))
+            {
+                Some(index) => {
+                    let (tag, symbols, _, _) = &lexenv_ref[index];
+                    let tag = *tag;
+                    let nth = symbols.iter().rposition(|lex| symbol.eq_(lex)).unwrap();
+
+                    // functions compiled inside tag's scope close over it
+                    for (_, _, closed, _) in lexenv_ref[index + 1..].iter_mut() {
+                        if !closed.iter().any(|func| func.eq_(&tag)) {
+                            closed.push(tag)
+                        }
+                    }
 
-                match Self::compile(mu, Cons::vlist(mu, &lex_ref)) {
-                    Ok(lexref) => return Ok(lexref),
-                    Err(e) => return Err(e),
+                    Some((tag, nth))
                 }
+                None => None,
             }
+        };
+
+        if let Some((tag, nth)) = lexical {
+            let lex_ref = vec![
+                Namespace::intern_symbol(mu, mu.mu_ns, "fr-ref".to_string(), Tag::nil()),
+                Fixnum::as_tag(tag.as_u64() as i64),
+                Fixnum::as_tag(nth as i64),
+            ];
+
+            return Self::compile(mu, Cons::vlist(mu, &lex_ref));
         }
 
         if Symbol::is_unbound(mu, symbol) {
@@ -250,7 +522,7 @@ impl Compiler {
             Type::Cons => {
                 let func = Cons::car(mu, expr);
                 let args = Cons::cdr(mu, expr);
-                match func.type_of() {
+                let form = match func.type_of() {
                     Type::Keyword => match Self::special_form(mu, func, args) {
                         Ok(form) => Ok(form),
                         Err(e) => Err(e),
@@ -274,7 +546,7 @@ impl Compiler {
                         Err(e) => Err(e),
                     },
                     Type::Cons => match Self::list(mu, args) {
-                        Ok(arglist) => match Self::compile(mu, func) {
+                        Ok(arglist) => match Self::function(mu, func) {
                             Ok(fn_) => match fn_.type_of() {
                                 Type::Function => Ok(Cons::new(fn_, arglist).evict(mu)),
                                 _ => Err(Exception::new(Condition::Type, "compile", func)),
@@ -284,6 +556,14 @@ impl Compiler {
                         Err(e) => Err(e),
                     },
                     _ => Err(Exception::new(Condition::Type, "compile", func)),
+                };
+
+                match form {
+                    Ok(form) => {
+                        Reader::relocate(mu, expr, form);
+                        Ok(form)
+                    }
+                    Err(e) => Err(Reader::locate_error(mu, expr, e)),
                 }
             }
             _ => Ok(expr),
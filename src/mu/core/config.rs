@@ -14,14 +14,25 @@ use crate::{
 pub struct Config {
     pub npages: usize,
     pub gcmode: GcMode,
+    pub depth: usize, // maximum dynamic frames
+    pub stack: usize, // evaluation stack in megabytes, 0 is the host's
 }
 
+// the default depth assumes a stack of the configured size, which
+// System::with_stack provides. without one, the host's stack is
+// typically 8MB, so the default is kept shallower
+const DEPTH: usize = 2048;
+const HOST_DEPTH: usize = 256;
+
 impl Mu {
     pub fn config(conf: String) -> Option<Config> {
         let mut config = Config {
             npages: 1024,
             gcmode: GcMode::Auto,
+            depth: DEPTH,
+            stack: 64,
         };
+        let mut depth = None;
 
         if !conf.is_empty() {
             for phrase in conf.split(',').collect::<Vec<&str>>() {
@@ -35,6 +46,14 @@ impl Mu {
                             Ok(n) => config.npages = n,
                             Err(_) => return None,
                         },
+                        "depth" => match arg.parse::<usize>() {
+                            Ok(n) => depth = Some(n),
+                            Err(_) => return None,
+                        },
+                        "stack" => match arg.parse::<usize>() {
+                            Ok(n) => config.stack = n,
+                            Err(_) => return None,
+                        },
                         "gcmode" => {
                             config.gcmode = match arg {
                                 "auto" => GcMode::Auto,
@@ -49,14 +68,32 @@ impl Mu {
             }
         }
 
+        config.depth = match depth {
+            Some(depth) => depth,
+            None if config.stack == 0 => HOST_DEPTH,
+            None => DEPTH,
+        };
+
         Some(config)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::core::mu::Mu;
+
     #[test]
     fn test() {
         assert_eq!(2 + 2, 4);
     }
+
+    #[test]
+    fn depth() {
+        for (conf, depth) in [("", 2048), ("stack:0", 256), ("depth:100,stack:0", 100)] {
+            match Mu::config(conf.to_string()) {
+                Some(config) => assert_eq!(config.depth, depth),
+                None => assert!(false),
+            }
+        }
+    }
 }
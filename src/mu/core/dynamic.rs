@@ -7,15 +7,23 @@
 //!    frame_push
 //!    frame_pop
 //!    frame_ref
+//!    frame inspection
+//!
+//! dynamic frames are numbered from the outermost, 0. mu:fr-top and
+//! mu:fr-up return a frame as (number . function), and a frame whose
+//! number no longer holds its function is out of range.
 use crate::{
+    allocators::bump_allocator::BumpAllocator,
     core::{
-        exception::{self},
+        exception::{self, Condition, Exception},
         frame::Frame,
-        mu::Mu,
-        types::Tag,
+        funcall::Core as _,
+        mu::{Core as _, Mu},
+        types::{Tag, Type},
     },
     types::{
         cons::{Cons, Core as _},
+        fixnum::Fixnum,
         vecimage::{TypedVec, VecType},
         vector::Core as _,
     },
@@ -23,6 +31,14 @@ use crate::{
 
 use futures::executor::block_on;
 
+// a dynamic frame's function, its (name, value) variables in lambda
+// list order, and its caller's frame. values bound by :let are named nil
+pub struct FrameInfo {
+    pub func: Tag,
+    pub vars: Vec<(Tag, Tag)>,
+    pub parent: Option<usize>,
+}
+
 impl Mu {
     pub fn dynamic_push(&self, func: Tag, offset: usize) {
         let mut dynamic_ref = block_on(self.dynamic.write());
@@ -36,7 +52,6 @@ impl Mu {
         dynamic_ref.pop();
     }
 
-    #[allow(dead_code)]
     pub fn dynamic_ref(&self, index: usize) -> (Tag, usize) {
         let dynamic_ref = block_on(self.dynamic.read());
 
@@ -44,37 +59,291 @@ impl Mu {
 
         (Tag::from_u64(func), offset)
     }
+
+    // the dynamic frames, outermost first
+    pub fn dynamic_frames(&self) -> Vec<(Tag, Vec<Tag>)> {
+        let dynamic_ref = block_on(self.dynamic.read());
+
+        dynamic_ref
+            .iter()
+            .map(|(func, offset)| {
+                let mut argv = Vec::new();
+
+                Frame::frame_stack_ref(self, Tag::from_u64(*func), *offset, &mut argv);
+                (
+                    Tag::from_u64(*func),
+                    argv.into_iter().map(Tag::from_u64).collect(),
+                )
+            })
+            .collect()
+    }
+
+    // the innermost dynamic frame
+    pub fn frame_top(&self) -> Option<usize> {
+        block_on(self.dynamic.read()).len().checked_sub(1)
+    }
+
+    pub fn frame_info(&self, frame: usize) -> Option<FrameInfo> {
+        let (func, offset) = *block_on(self.dynamic.read()).get(frame)?;
+        let mut argv = Vec::new();
+
+        Frame::frame_stack_ref(self, Tag::from_u64(func), offset, &mut argv);
+
+        let names_ref = block_on(self.lambda_names.read());
+        let names = names_ref.get(&func);
+
+        Some(FrameInfo {
+            func: Tag::from_u64(func),
+            vars: argv
+                .iter()
+                .enumerate()
+                .map(|(nth, value)| {
+                    let name = names.and_then(|names| names.get(nth).copied());
+
+                    (name.unwrap_or(Tag::nil()), Tag::from_u64(*value))
+                })
+                .collect(),
+            parent: frame.checked_sub(1),
+        })
+    }
+
+    // keep the argument names of functions the mark phase reached.
+    // a name's value may reach another function, so names are marked
+    // until no more functions are live
+    pub fn gc_lambda_names(&self) {
+        fn is_marked(heap: &BumpAllocator, func: u64) -> bool {
+            match Tag::from_u64(func) {
+                Tag::Indirect(indirect) => heap
+                    .get_image_refbit(indirect.image_id() as usize)
+                    .unwrap_or(false),
+                Tag::Direct(_) => false,
+            }
+        }
+
+        let mut nlive = 0;
+
+        loop {
+            let live = {
+                let heap_ref = block_on(self.heap.read());
+
+                block_on(self.lambda_names.read())
+                    .iter()
+                    .filter(|(func, _)| is_marked(&heap_ref, **func))
+                    .map(|(_, names)| names.clone())
+                    .collect::<Vec<Vec<Tag>>>()
+            };
+
+            if live.len() == nlive {
+                break;
+            }
+
+            nlive = live.len();
+
+            for symbol in live.iter().flatten() {
+                self.gc_mark(*symbol)
+            }
+        }
+
+        let heap_ref = block_on(self.heap.read());
+
+        block_on(self.lambda_names.write()).retain(|func, _| is_marked(&heap_ref, *func))
+    }
+
+    // ((function . #(argument...)) ...)
+    pub fn frames_list(&self, frames: &[(Tag, Vec<Tag>)]) -> Tag {
+        let frames = frames
+            .iter()
+            .map(|(func, argv)| {
+                let values = TypedVec::<Vec<Tag>> { vec: argv.clone() }
+                    .vec
+                    .to_vector()
+                    .evict(self);
+
+                Cons::new(*func, values).evict(self)
+            })
+            .collect::<Vec<Tag>>();
+
+        Cons::vlist(self, &frames)
+    }
+
+    // the symbol a function is the value of, if there is one
+    pub fn frame_symbol(&self, func: Tag) -> Option<Tag> {
+        block_on(self.fn_index.read()).get(&func.as_u64()).copied()
+    }
+
+    // the dynamic frames with the symbols their functions are bound
+    // to, or nil, taken when an exception is raised
+    pub fn backtrace(&self) -> Vec<(Tag, Tag, Vec<Tag>)> {
+        self.dynamic_frames()
+            .into_iter()
+            .map(|(func, argv)| (func, self.frame_symbol(func).unwrap_or(Tag::nil()), argv))
+            .collect()
+    }
+
+    // ((function symbol . #(argument...)) ...)
+    pub fn backtrace_list(&self, backtrace: &[(Tag, Tag, Vec<Tag>)]) -> Tag {
+        let frames = backtrace
+            .iter()
+            .map(|(func, symbol, argv)| {
+                let values = TypedVec::<Vec<Tag>> { vec: argv.clone() }
+                    .vec
+                    .to_vector()
+                    .evict(self);
+
+                Cons::new(*func, Cons::new(*symbol, values).evict(self)).evict(self)
+            })
+            .collect::<Vec<Tag>>();
+
+        Cons::vlist(self, &frames)
+    }
 }
 
 pub trait MuFunction {
     fn mu_frames(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+    fn mu_fr_top(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+    fn mu_fr_up(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+    fn mu_fr_fn(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+    fn mu_fr_vars(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+}
+
+// (number . function)
+fn frame_tag(mu: &Mu, frame: usize) -> Tag {
+    let (func, _) = mu.dynamic_ref(frame);
+
+    Cons::new(Fixnum::as_tag(frame as i64), func).evict(mu)
+}
+
+// the frame a (number . function) names, if it is still on the stack
+fn frame_arg(mu: &Mu, fp: &mut Frame, fn_name: &str) -> exception::Result<FrameInfo> {
+    let frame = fp.argv[0];
+
+    match mu.fp_argv_check(fn_name, &[Type::Cons], fp) {
+        Ok(_) => {
+            let nth = Cons::car(mu, frame);
+            let func = Cons::cdr(mu, frame);
+
+            if nth.type_of() != Type::Fixnum || func.type_of() != Type::Function {
+                return Err(Exception::new(Condition::Type, fn_name, frame));
+            }
+
+            match usize::try_from(Fixnum::as_i64(nth)) {
+                Ok(nth) => match mu.frame_info(nth) {
+                    Some(info) if info.func.eq_(&func) => Ok(info),
+                    _ => Err(Exception::new(Condition::Range, fn_name, frame)),
+                },
+                Err(_) => Err(Exception::new(Condition::Range, fn_name, frame)),
+            }
+        }
+        Err(e) => Err(e),
+    }
 }
 
 impl MuFunction for Mu {
     fn mu_frames(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
-        let env_ref = block_on(mu.dynamic.read());
+        fp.value = mu.frames_list(&mu.dynamic_frames());
 
-        let mut frames = Vec::new();
+        Ok(())
+    }
 
-        for (func, offset) in env_ref.iter() {
-            let mut argv = Vec::new();
+    // (mu:fr-top), the innermost frame or nil
+    fn mu_fr_top(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        fp.value = match mu.frame_top() {
+            Some(frame) => frame_tag(mu, frame),
+            None => Tag::nil(),
+        };
 
-            Frame::frame_stack_ref(mu, Tag::from_u64(*func), *offset, &mut argv);
-            let vec = argv.into_iter().map(Tag::from_u64).collect();
-            let values = TypedVec::<Vec<Tag>> { vec }.vec.to_vector().evict(mu);
+        Ok(())
+    }
 
-            frames.push(Cons::new(Tag::from_u64(*func), values).evict(mu))
-        }
+    // (mu:fr-up frame), the frame's caller or nil
+    fn mu_fr_up(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        fp.value = match frame_arg(mu, fp, "fr-up") {
+            Ok(info) => match info.parent {
+                Some(frame) => frame_tag(mu, frame),
+                None => Tag::nil(),
+            },
+            Err(e) => return Err(e),
+        };
+
+        Ok(())
+    }
+
+    // (mu:fr-fn frame), the frame's function
+    fn mu_fr_fn(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        fp.value = match frame_arg(mu, fp, "fr-fn") {
+            Ok(info) => info.func,
+            Err(e) => return Err(e),
+        };
+
+        Ok(())
+    }
+
+    // (mu:fr-vars frame), ((name . value) ...)
+    fn mu_fr_vars(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        fp.value = match frame_arg(mu, fp, "fr-vars") {
+            Ok(info) => {
+                let vars = info
+                    .vars
+                    .iter()
+                    .map(|(name, value)| Cons::new(*name, *value).evict(mu))
+                    .collect::<Vec<Tag>>();
+
+                Cons::vlist(mu, &vars)
+            }
+            Err(e) => return Err(e),
+        };
 
-        fp.value = Cons::vlist(mu, &frames);
         Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::core::{
+        compiler::Compiler,
+        mu::{Core, Mu},
+        stream,
+        types::Tag,
+    };
+    use crate::types::{stream::Core as _, streambuilder::StreamBuilder};
+    use futures::executor::block_on;
+
     #[test]
     fn dynamic() {
         assert_eq!(2 + 2, 4);
     }
+
+    #[test]
+    fn lambda_names() {
+        let config = match Mu::config("".to_string()) {
+            Some(config) => config,
+            None => return assert!(false),
+        };
+
+        let mu: &Mu = &Core::new(&config);
+        let stream = match StreamBuilder::new()
+            .string("(:lambda (x) x)".to_string())
+            .input()
+            .build(mu)
+        {
+            Ok(stream) => stream.evict(mu),
+            Err(_) => return assert!(false),
+        };
+
+        let lambda = match <Mu as stream::Core>::read(mu, stream, true, Tag::nil(), false) {
+            Ok(expr) => match Compiler::compile(mu, expr) {
+                Ok(lambda) => lambda,
+                Err(_) => return assert!(false),
+            },
+            Err(_) => return assert!(false),
+        };
+
+        assert!(block_on(mu.lambda_names.read()).contains_key(&lambda.as_u64()));
+
+        // nothing refers to the lambda, its names go with it
+        match mu.gc() {
+            Ok(_) => assert!(!block_on(mu.lambda_names.read()).contains_key(&lambda.as_u64())),
+            Err(_) => assert!(false),
+        }
+    }
 }
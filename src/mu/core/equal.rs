@@ -0,0 +1,261 @@
+//  SPDX-FileCopyrightText: Copyright 2022 James M. Putnam (putnamjm.design@gmail.com)
+//  SPDX-License-Identifier: MIT
+
+//! mu structural equality
+use {
+    crate::{
+        core::{
+            exception,
+            frame::Frame,
+            mu::Mu,
+            types::{Tag, Type},
+        },
+        types::{
+            char::Char,
+            cons::Cons,
+            fixnum::Fixnum,
+            float::Float,
+            map::Map,
+            struct_::Struct,
+            symbol::{Core as _, Symbol},
+            vecimage::VectorIter,
+            vector::{Core as _, Vector},
+        },
+    },
+    std::hash::{Hash, Hasher},
+};
+
+// fnv-1a, hashes don't change between runs or rust releases
+pub struct FnvHasher(u64);
+
+impl Default for FnvHasher {
+    fn default() -> Self {
+        FnvHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(0x100_0000_01b3)
+        }
+    }
+}
+
+pub trait Core {
+    fn equal(_: &Mu, _: Tag, _: Tag) -> bool;
+    fn equalp(_: &Mu, _: Tag, _: Tag) -> bool;
+    fn hash(_: &Mu, _: Tag) -> u64;
+}
+
+impl Tag {
+    // conses are compared iteratively along the cdr so long lists
+    // don't exhaust the rust stack
+    fn equal_(mu: &Mu, tag: Tag, tag1: Tag, fold: bool) -> bool {
+        let mut tag = tag;
+        let mut tag1 = tag1;
+
+        loop {
+            if tag.eq_(&tag1) {
+                return true;
+            }
+
+            match (tag.type_of(), tag1.type_of()) {
+                (Type::Cons, Type::Cons) => {
+                    if !Self::equal_(mu, Cons::car(mu, tag), Cons::car(mu, tag1), fold) {
+                        return false;
+                    }
+
+                    tag = Cons::cdr(mu, tag);
+                    tag1 = Cons::cdr(mu, tag1)
+                }
+                (Type::Vector, Type::Vector) => return Self::vector_equal(mu, tag, tag1, fold),
+                (Type::Struct, Type::Struct) => {
+                    return Struct::stype(mu, tag).eq_(&Struct::stype(mu, tag1))
+                        && Self::equal_(
+                            mu,
+                            Struct::vector(mu, tag),
+                            Struct::vector(mu, tag1),
+                            fold,
+                        )
+                }
+                // both maps have to compare keys the same way for
+                // a lookup in one to mean the same thing in the other
+                (Type::Map, Type::Map) => {
+                    let pairs = Map::pairs(mu, tag);
+
+                    return Map::is_equal(mu, tag) == Map::is_equal(mu, tag1)
+                        && pairs.len() == Map::size(mu, tag1)
+                        && pairs
+                            .iter()
+                            .all(|(key, value)| match Map::map_ref(mu, tag1, *key) {
+                                Some(value1) => Self::equal_(mu, *value, value1, fold),
+                                None => false,
+                            });
+                }
+                (Type::Char, Type::Char) if fold => {
+                    return Char::as_char(mu, tag)
+                        .to_lowercase()
+                        .eq(Char::as_char(mu, tag1).to_lowercase())
+                }
+                (Type::Fixnum | Type::Float, Type::Fixnum | Type::Float) if fold => {
+                    return Self::as_f64(mu, tag) == Self::as_f64(mu, tag1)
+                }
+                _ => return false,
+            }
+        }
+    }
+
+    fn vector_equal(mu: &Mu, vector: Tag, vector1: Tag, fold: bool) -> bool {
+        let vtype = Vector::type_of(mu, vector);
+        let vtype1 = Vector::type_of(mu, vector1);
+
+        if Vector::length(mu, vector) != Vector::length(mu, vector1) {
+            return false;
+        }
+
+        match (vtype, vtype1) {
+            (Type::Char, Type::Char) if !fold => {
+                Vector::as_string(mu, vector) == Vector::as_string(mu, vector1)
+            }
+            _ if vtype == vtype1 || fold => VectorIter::new(mu, vector)
+                .zip(VectorIter::new(mu, vector1))
+                .all(|(elt, elt1)| Self::equal_(mu, elt, elt1, fold)),
+            _ => false,
+        }
+    }
+
+    fn as_f64(mu: &Mu, number: Tag) -> f64 {
+        match number.type_of() {
+            Type::Fixnum => Fixnum::as_i64(number) as f64,
+            Type::Float => Float::as_f32(mu, number) as f64,
+            _ => panic!(),
+        }
+    }
+
+    fn hash_(mu: &Mu, tag: Tag, hasher: &mut FnvHasher) {
+        match tag.type_of() {
+            Type::Cons => {
+                let mut list = tag;
+
+                while list.type_of() == Type::Cons {
+                    (Type::Cons as u8).hash(hasher);
+                    Self::hash_(mu, Cons::car(mu, list), hasher);
+                    list = Cons::cdr(mu, list)
+                }
+
+                Self::hash_(mu, list, hasher)
+            }
+            Type::Vector => match Vector::type_of(mu, tag) {
+                Type::Char => Vector::as_string(mu, tag).hash(hasher),
+                vtype => {
+                    (vtype as u8).hash(hasher);
+                    for elt in VectorIter::new(mu, tag) {
+                        Self::hash_(mu, elt, hasher)
+                    }
+                }
+            },
+            // symbols are hashed by name, not by where they live in the heap
+            Type::Symbol => {
+                (Type::Symbol as u8).hash(hasher);
+                Symbol::namespace(mu, tag).as_u64().hash(hasher);
+                Self::hash_(mu, Symbol::name(mu, tag), hasher)
+            }
+            Type::Struct => {
+                Struct::stype(mu, tag).as_u64().hash(hasher);
+                Self::hash_(mu, Struct::vector(mu, tag), hasher)
+            }
+            Type::Map => {
+                // map entries are unordered, combine them commutatively
+                let pairs = Map::pairs(mu, tag);
+
+                (Type::Map as u8).hash(hasher);
+                pairs
+                    .iter()
+                    .fold(pairs.len() as u64, |hash, (key, value)| {
+                        let mut pair_hasher = FnvHasher::default();
+
+                        Self::hash_(mu, *key, &mut pair_hasher);
+                        Self::hash_(mu, *value, &mut pair_hasher);
+                        hash.wrapping_add(pair_hasher.finish())
+                    })
+                    .hash(hasher)
+            }
+            _ => tag.as_u64().hash(hasher),
+        }
+    }
+}
+
+impl Core for Tag {
+    fn equal(mu: &Mu, tag: Tag, tag1: Tag) -> bool {
+        Self::equal_(mu, tag, tag1, false)
+    }
+
+    fn equalp(mu: &Mu, tag: Tag, tag1: Tag) -> bool {
+        Self::equal_(mu, tag, tag1, true)
+    }
+
+    fn hash(mu: &Mu, tag: Tag) -> u64 {
+        let mut hasher = FnvHasher::default();
+
+        Self::hash_(mu, tag, &mut hasher);
+        hasher.finish()
+    }
+}
+
+pub trait MuFunction {
+    fn mu_equal(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+    fn mu_equalp(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+    fn mu_hash(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+}
+
+impl MuFunction for Tag {
+    fn mu_equal(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        fp.value = if Self::equal(mu, fp.argv[0], fp.argv[1]) {
+            Symbol::keyword("t")
+        } else {
+            Tag::nil()
+        };
+
+        Ok(())
+    }
+
+    fn mu_equalp(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        fp.value = if Self::equalp(mu, fp.argv[0], fp.argv[1]) {
+            Symbol::keyword("t")
+        } else {
+            Tag::nil()
+        };
+
+        Ok(())
+    }
+
+    fn mu_hash(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        // fixnums are 56 bits, keep the hash positive
+        fp.value = Fixnum::as_tag((Self::hash(mu, fp.argv[0]) >> 9) as i64);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {crate::core::equal::FnvHasher, std::hash::Hasher};
+
+    #[test]
+    fn equal() {
+        assert_eq!(true, true)
+    }
+
+    #[test]
+    fn fnv() {
+        let mut hasher = FnvHasher::default();
+
+        hasher.write(b"a");
+        assert_eq!(hasher.finish(), 0xaf63_dc4c_8601_ec8c)
+    }
+}
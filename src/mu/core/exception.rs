@@ -5,15 +5,22 @@
 //!    Condition
 //!    Exception
 //!    `Result<Exception>`
+//!    user conditions
 use {
     crate::{
         core::{
             frame::Frame,
             funcall::Core as _,
             mu::{Core as _, Mu},
+            reader::Location,
             types::{Tag, Type},
         },
-        types::symbol::{Core as _, Symbol},
+        types::{
+            cons::{Cons, Core as _},
+            fixnum::Fixnum,
+            function::Function,
+            symbol::{Core as _, Symbol},
+        },
     },
     std::fmt,
 };
@@ -27,6 +34,10 @@ pub struct Exception {
     pub object: Tag,
     pub condition: Condition,
     pub source: Tag,
+    pub location: Option<Location>,
+    pub backtrace: Vec<(Tag, Tag, Vec<Tag>)>, // (function, symbol or nil, arguments), outermost first
+    pub reason: Tag,                          // string or nil
+    pub signaled: bool,                       // handlers have run
 }
 
 #[derive(Eq, PartialEq, Clone, Debug)]
@@ -40,13 +51,18 @@ pub enum Condition {
     Namespace,
     Range,
     Read,
+    Restart,
+    Return,
+    Stack,
     Stream,
     Syntax,
+    Throw,
     Type,
     Unbound,
     Under,
     Write,
     ZeroDivide,
+    User(u64), // keyword defined by mu:defcond
 }
 
 lazy_static! {
@@ -61,8 +77,12 @@ lazy_static! {
         (Symbol::keyword("ns"), Condition::Namespace),
         (Symbol::keyword("range"), Condition::Range),
         (Symbol::keyword("read"), Condition::Read),
+        (Symbol::keyword("restart"), Condition::Restart),
+        (Symbol::keyword("return"), Condition::Return),
+        (Symbol::keyword("stack"), Condition::Stack),
         (Symbol::keyword("stream"), Condition::Stream),
         (Symbol::keyword("syntax"), Condition::Syntax),
+        (Symbol::keyword("throw"), Condition::Throw),
         (Symbol::keyword("type"), Condition::Type),
         (Symbol::keyword("unbound"), Condition::Unbound),
         (Symbol::keyword("under"), Condition::Under),
@@ -88,10 +108,144 @@ impl Exception {
             object,
             condition,
             source: Symbol::keyword(src),
+            location: None,
+            backtrace: Vec::new(),
+            reason: Tag::nil(),
+            signaled: false,
+        }
+    }
+
+    // a non-local exit to block
+    pub fn return_(block: Tag, value: Tag) -> Self {
+        Exception {
+            object: value,
+            condition: Condition::Return,
+            source: block,
+            location: None,
+            backtrace: Vec::new(),
+            reason: Tag::nil(),
+            signaled: false,
+        }
+    }
+
+    // a non-local exit to the nth established restart
+    pub fn restart_(nth: usize, args: Tag) -> Self {
+        Exception {
+            object: args,
+            condition: Condition::Restart,
+            source: Fixnum::as_tag(nth as i64),
+            location: None,
+            backtrace: Vec::new(),
+            reason: Tag::nil(),
+            signaled: false,
+        }
+    }
+
+    // a non-local exit to the catch established for tag
+    pub fn throw_(tag: Tag, value: Tag) -> Self {
+        Exception {
+            object: value,
+            condition: Condition::Throw,
+            source: tag,
+            location: None,
+            backtrace: Vec::new(),
+            reason: Tag::nil(),
+            signaled: false,
+        }
+    }
+
+    // non-local exits aren't errors, they are neither signaled nor handled
+    pub fn is_exit(&self) -> bool {
+        matches!(
+            self.condition,
+            Condition::Return | Condition::Restart | Condition::Throw
+        )
+    }
+
+    // the arguments a handler is applied to, handlers that take a fourth
+    // argument get the backtrace, and a fifth the reason
+    fn handler_args(mu: &Mu, handler: Tag, e: &Exception) -> Vec<Tag> {
+        let lambda_list = Function::lambda_list(mu, handler);
+        let mut args = vec![
+            e.object,
+            Self::map_condkey(e.condition.clone()).unwrap(),
+            e.source,
+        ];
+
+        if lambda_list.accepts(4) || lambda_list.accepts(5) {
+            args.push(mu.backtrace_list(&e.backtrace))
+        }
+
+        if lambda_list.accepts(5) {
+            args.push(e.reason)
+        }
+
+        args
+    }
+
+    // apply a handler to an exception. handlers may be applied at the
+    // depth limit, and can use frame::HANDLER_FRAMES more
+    fn apply_handler(mu: &Mu, handler: Tag, e: &Exception) -> Result<Tag> {
+        *block_on(mu.handling.write()) += 1;
+
+        let value = mu.apply_(handler, Self::handler_args(mu, handler, e));
+
+        *block_on(mu.handling.write()) -= 1;
+
+        value
+    }
+
+    // run the mu:handle handlers, innermost first, in the frames the
+    // exception was raised in. a handler declines by returning, and runs
+    // with only the handlers established outside of it. handlers outside
+    // the innermost mu:with-ex, marked by nil, aren't run
+    pub fn signal(mu: &Mu, e: Exception) -> Exception {
+        if e.signaled || e.is_exit() {
+            return e;
+        }
+
+        let e = Exception {
+            signaled: true,
+            ..e
+        };
+        let handlers = block_on(mu.handlers.read()).clone();
+
+        for (nth, handler) in handlers.iter().enumerate().rev() {
+            if handler.null_() {
+                break;
+            }
+
+            let inner = block_on(mu.handlers.write()).split_off(nth);
+            let value = Self::apply_handler(mu, *handler, &e);
+
+            block_on(mu.handlers.write()).extend(inner);
+
+            // the handler's exception has been signaled to the
+            // handlers outside it, and replaces ours
+            if let Err(e) = value {
+                return Exception {
+                    signaled: true,
+                    ..e
+                };
+            }
+        }
+
+        e
+    }
+
+    // the conditions mu:raise and mu:signal make, the non-local exits
+    // are only made by the functions that unwind to them
+    fn raise_condition(mu: &Mu, keyword: Tag) -> Result<Condition> {
+        match Self::map_condition(mu, keyword) {
+            Ok(Condition::Restart | Condition::Throw) => {
+                Err(Exception::new(Condition::Type, "cond", keyword))
+            }
+            Ok(cond) => Ok(cond),
+            Err(e) => Err(e),
         }
     }
 
-    fn map_condition(keyword: Tag) -> Result<Condition> {
+    fn map_condition(mu: &Mu, keyword: Tag) -> Result<Condition> {
         #[allow(clippy::unnecessary_to_owned)]
         let condmap = CONDMAP
             .to_vec()
@@ -100,15 +254,50 @@ impl Exception {
 
         match condmap {
             Some(entry) => Ok(entry.1),
-            _ => Err(Exception::new(
-                Condition::Syntax,
-                "exception::map_condition",
-                keyword,
-            )),
+            _ => {
+                let conditions_ref = block_on(mu.conditions.read());
+
+                if keyword.type_of() == Type::Keyword
+                    && conditions_ref.contains_key(&keyword.as_u64())
+                {
+                    Ok(Condition::User(keyword.as_u64()))
+                } else {
+                    Err(Exception::new(Condition::Syntax, "cond", keyword))
+                }
+            }
+        }
+    }
+
+    // the parent of a user condition, builtin conditions have none
+    fn parent(mu: &Mu, keyword: Tag) -> Tag {
+        let conditions_ref = block_on(mu.conditions.read());
+
+        match conditions_ref.get(&keyword.as_u64()) {
+            Some(parent) => *parent,
+            None => Tag::nil(),
+        }
+    }
+
+    // is condition ancestor or one of its descendants
+    pub fn is_a(mu: &Mu, keyword: Tag, ancestor: Tag) -> bool {
+        let mut keyword = keyword;
+
+        while !keyword.null_() {
+            if keyword.eq_(&ancestor) {
+                return true;
+            }
+
+            keyword = Self::parent(mu, keyword)
         }
+
+        false
     }
 
-    fn map_condkey(cond: Condition) -> Result<Tag> {
+    pub fn map_condkey(cond: Condition) -> Result<Tag> {
+        if let Condition::User(keyword) = cond {
+            return Ok(Tag::from_u64(keyword));
+        }
+
         #[allow(clippy::unnecessary_to_owned)]
         let condmap = CONDMAP
             .to_vec()
@@ -125,6 +314,16 @@ impl Exception {
 pub trait MuFunction {
     fn mu_with_ex(mu: &Mu, fp: &mut Frame) -> Result<()>;
     fn mu_raise(mu: &Mu, fp: &mut Frame) -> Result<()>;
+    fn mu_unwind(mu: &Mu, fp: &mut Frame) -> Result<()>;
+    fn mu_catch(mu: &Mu, fp: &mut Frame) -> Result<()>;
+    fn mu_throw(mu: &Mu, fp: &mut Frame) -> Result<()>;
+    fn mu_defcond(mu: &Mu, fp: &mut Frame) -> Result<()>;
+    fn mu_condp(mu: &Mu, fp: &mut Frame) -> Result<()>;
+    fn mu_signal(mu: &Mu, fp: &mut Frame) -> Result<()>;
+    fn mu_handle(mu: &Mu, fp: &mut Frame) -> Result<()>;
+    fn mu_with_rs(mu: &Mu, fp: &mut Frame) -> Result<()>;
+    fn mu_invoke(mu: &Mu, fp: &mut Frame) -> Result<()>;
+    fn mu_rs_list(mu: &Mu, fp: &mut Frame) -> Result<()>;
 }
 
 impl MuFunction for Exception {
@@ -133,7 +332,7 @@ impl MuFunction for Exception {
         let condition = fp.argv[1];
 
         match mu.fp_argv_check("raise", &[Type::T, Type::Keyword], fp) {
-            Ok(_) => match Self::map_condition(condition) {
+            Ok(_) => match Self::raise_condition(mu, condition) {
                 Ok(cond) => Err(Self::new(cond, "raise", src)),
                 Err(_) => Err(Self::new(Condition::Type, "raise", condition)),
             },
@@ -145,40 +344,315 @@ impl MuFunction for Exception {
         let handler = fp.argv[0];
         let thunk = fp.argv[1];
 
-        fp.value = match mu.fp_argv_check("with-ex", &[Type::Function, Type::Function], fp) {
+        match mu.fp_argv_check("with-ex", &[Type::Function, Type::Function], fp) {
             Ok(_) => {
                 {
-                    let dynamic_ref = block_on(mu.dynamic.read());
                     let mut exception_ref = block_on(mu.exception.write());
 
-                    exception_ref.push(dynamic_ref.len())
+                    exception_ref.push(Frame::unwind_mark(mu))
                 }
 
-                match mu.apply(thunk, Tag::nil()) {
-                    Ok(value) => value,
+                // with-ex handles what thunk raises, the handlers
+                // outside it aren't signaled
+                let nth = {
+                    let mut handlers_ref = block_on(mu.handlers.write());
+
+                    handlers_ref.push(Tag::nil());
+                    handlers_ref.len() - 1
+                };
+
+                let value = mu.apply(thunk, Tag::nil());
+
+                block_on(mu.handlers.write()).truncate(nth);
+
+                let mark = match block_on(mu.exception.write()).pop() {
+                    Some(mark) => mark,
+                    None => panic!("exception stack underflow"),
+                };
+
+                match value {
+                    Ok(value) => fp.value = value,
                     Err(e) => {
-                        let args =
-                            vec![e.object, Self::map_condkey(e.condition).unwrap(), e.source];
-                        match mu.apply_(handler, args) {
-                            Ok(value) => {
-                                let mut dynamic_ref = block_on(mu.dynamic.write());
-                                let mut exception_ref = block_on(mu.exception.write());
-
-                                match exception_ref.pop() {
-                                    Some(len) => {
-                                        dynamic_ref.resize(len, (0, 0));
-                                        value
-                                    }
-                                    None => panic!("dynamic stack underflow"),
-                                }
-                            }
+                        // the handler runs in with-ex's frames
+                        Frame::unwind(mu, &mark);
+
+                        // non-local exits aren't handled
+                        if e.is_exit() {
+                            return Err(e);
+                        }
+
+                        match Self::apply_handler(mu, handler, &e) {
+                            Ok(value) => fp.value = value,
                             Err(e) => return Err(e),
                         }
                     }
                 }
             }
             Err(e) => return Err(e),
-        };
+        }
+
+        Ok(())
+    }
+
+    // (mu:unwind-protect thunk cleanup), cleanup is applied
+    // however thunk exits. an exception raised by cleanup replaces thunk's
+    fn mu_unwind(mu: &Mu, fp: &mut Frame) -> Result<()> {
+        let thunk = fp.argv[0];
+        let cleanup = fp.argv[1];
+
+        match mu.fp_argv_check("unwind", &[Type::Function, Type::Function], fp) {
+            Ok(_) => {
+                let mark = Frame::unwind_mark(mu);
+                let value = mu.apply(thunk, Tag::nil());
+
+                if value.is_err() {
+                    Frame::unwind(mu, &mark)
+                }
+
+                match mu.apply(cleanup, Tag::nil()) {
+                    Ok(_) => match value {
+                        Ok(value) => fp.value = value,
+                        Err(e) => return Err(e),
+                    },
+                    Err(e) => return Err(e),
+                }
+            }
+            Err(e) => return Err(e),
+        }
+
+        Ok(())
+    }
+
+    // (mu:catch tag thunk), the value of thunk or of a mu:throw to tag
+    fn mu_catch(mu: &Mu, fp: &mut Frame) -> Result<()> {
+        let tag = fp.argv[0];
+        let thunk = fp.argv[1];
+
+        match mu.fp_argv_check("catch", &[Type::T, Type::Function], fp) {
+            Ok(_) => {
+                let mark = Frame::unwind_mark(mu);
+                let nth = {
+                    let mut catches_ref = block_on(mu.catches.write());
+
+                    catches_ref.push(tag);
+                    catches_ref.len() - 1
+                };
+
+                let value = mu.apply(thunk, Tag::nil());
+
+                block_on(mu.catches.write()).truncate(nth);
+
+                match value {
+                    Ok(value) => fp.value = value,
+                    Err(e) if e.condition == Condition::Throw && e.source.eq_(&tag) => {
+                        Frame::unwind(mu, &mark);
+                        fp.value = e.object
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            Err(e) => return Err(e),
+        }
+
+        Ok(())
+    }
+
+    // (mu:throw tag value), a non-local exit to the innermost catch of tag
+    fn mu_throw(mu: &Mu, fp: &mut Frame) -> Result<()> {
+        let tag = fp.argv[0];
+        let value = fp.argv[1];
+
+        let catches_ref = block_on(mu.catches.read());
+
+        if catches_ref.iter().any(|catch| tag.eq_(catch)) {
+            Err(Self::throw_(tag, value))
+        } else {
+            Err(Self::new(Condition::Range, "throw", tag))
+        }
+    }
+
+    // (mu:defcond name parent), parent is a condition or nil
+    fn mu_defcond(mu: &Mu, fp: &mut Frame) -> Result<()> {
+        let name = fp.argv[0];
+        let parent = fp.argv[1];
+
+        match mu.fp_argv_check("defcond", &[Type::Keyword, Type::T], fp) {
+            Ok(_) => {
+                if CONDMAP.iter().any(|cond| name.eq_(&cond.0)) {
+                    return Err(Self::new(Condition::Range, "defcond", name));
+                }
+
+                if !parent.null_() {
+                    match Self::map_condition(mu, parent) {
+                        Ok(_) if Self::is_a(mu, parent, name) => {
+                            return Err(Self::new(Condition::Range, "defcond", parent))
+                        }
+                        Ok(_) => (),
+                        Err(_) => return Err(Self::new(Condition::Type, "defcond", parent)),
+                    }
+                }
+
+                let mut conditions_ref = block_on(mu.conditions.write());
+
+                conditions_ref.insert(name.as_u64(), parent);
+                fp.value = name
+            }
+            Err(e) => return Err(e),
+        }
+
+        Ok(())
+    }
+
+    fn mu_condp(mu: &Mu, fp: &mut Frame) -> Result<()> {
+        let condition = fp.argv[0];
+        let ancestor = fp.argv[1];
+
+        match mu.fp_argv_check("condp", &[Type::Keyword, Type::Keyword], fp) {
+            Ok(_) => {
+                for keyword in [condition, ancestor] {
+                    if Self::map_condition(mu, keyword).is_err() {
+                        return Err(Self::new(Condition::Type, "condp", keyword));
+                    }
+                }
+
+                fp.value = if Self::is_a(mu, condition, ancestor) {
+                    Symbol::keyword("t")
+                } else {
+                    Tag::nil()
+                }
+            }
+            Err(e) => return Err(e),
+        }
+
+        Ok(())
+    }
+
+    // (mu:signal object condition reason), raise with a reason string
+    fn mu_signal(mu: &Mu, fp: &mut Frame) -> Result<()> {
+        let object = fp.argv[0];
+        let condition = fp.argv[1];
+        let reason = fp.argv[2];
+
+        match mu.fp_argv_check("signal", &[Type::T, Type::Keyword, Type::String], fp) {
+            Ok(_) => match Self::raise_condition(mu, condition) {
+                Ok(cond) => Err(Exception {
+                    reason,
+                    ..Self::new(cond, "signal", object)
+                }),
+                Err(_) => Err(Self::new(Condition::Type, "signal", condition)),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    // (mu:handle handler thunk), handler is applied to exceptions raised
+    // in thunk before they unwind, and declines them by returning
+    fn mu_handle(mu: &Mu, fp: &mut Frame) -> Result<()> {
+        let handler = fp.argv[0];
+        let thunk = fp.argv[1];
+
+        match mu.fp_argv_check("handle", &[Type::Function, Type::Function], fp) {
+            Ok(_) => {
+                let nth = {
+                    let mut handlers_ref = block_on(mu.handlers.write());
+
+                    handlers_ref.push(handler);
+                    handlers_ref.len() - 1
+                };
+
+                let value = mu.apply(thunk, Tag::nil());
+
+                block_on(mu.handlers.write()).truncate(nth);
+
+                match value {
+                    Ok(value) => fp.value = value,
+                    Err(e) => return Err(e),
+                }
+            }
+            Err(e) => return Err(e),
+        }
+
+        Ok(())
+    }
+
+    // (mu:with-rs name restart thunk) establishes a restart around thunk,
+    // mu:invoke unwinds to with-rs and applies restart to its arguments
+    fn mu_with_rs(mu: &Mu, fp: &mut Frame) -> Result<()> {
+        let name = fp.argv[0];
+        let restart = fp.argv[1];
+        let thunk = fp.argv[2];
+
+        match mu.fp_argv_check(
+            "with-rs",
+            &[Type::Keyword, Type::Function, Type::Function],
+            fp,
+        ) {
+            Ok(_) => {
+                let mark = Frame::unwind_mark(mu);
+                let nth = {
+                    let mut restarts_ref = block_on(mu.restarts.write());
+
+                    restarts_ref.push((name, restart));
+                    restarts_ref.len() - 1
+                };
+
+                let value = mu.apply(thunk, Tag::nil());
+
+                block_on(mu.restarts.write()).truncate(nth);
+
+                match value {
+                    Ok(value) => fp.value = value,
+                    Err(e)
+                        if e.condition == Condition::Restart
+                            && e.source.eq_(&Fixnum::as_tag(nth as i64)) =>
+                    {
+                        Frame::unwind(mu, &mark);
+
+                        match mu.apply(restart, e.object) {
+                            Ok(value) => fp.value = value,
+                            Err(e) => return Err(e),
+                        }
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            Err(e) => return Err(e),
+        }
+
+        Ok(())
+    }
+
+    // (mu:invoke name args), a non-local exit to the innermost restart
+    // established with name
+    fn mu_invoke(mu: &Mu, fp: &mut Frame) -> Result<()> {
+        let name = fp.argv[0];
+        let args = fp.argv[1];
+
+        match mu.fp_argv_check("invoke", &[Type::Keyword, Type::List], fp) {
+            Ok(_) => {
+                let restarts_ref = block_on(mu.restarts.read());
+
+                match restarts_ref
+                    .iter()
+                    .rposition(|(restart, _)| name.eq_(restart))
+                {
+                    Some(nth) => Err(Self::restart_(nth, args)),
+                    None => Err(Self::new(Condition::Range, "invoke", name)),
+                }
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    // (mu:rs-list), the names of the established restarts, innermost first
+    fn mu_rs_list(mu: &Mu, fp: &mut Frame) -> Result<()> {
+        let names = block_on(mu.restarts.read())
+            .iter()
+            .rev()
+            .map(|(name, _)| *name)
+            .collect::<Vec<Tag>>();
+
+        fp.value = Cons::vlist(mu, &names);
 
         Ok(())
     }
@@ -7,17 +7,23 @@
 //!    frame_push
 //!    frame_pop
 //!    frame_ref
+//!    closures
+//!    let and block
 use crate::{
     core::{
+        bytecode::Bytecode,
         exception::{self, Condition, Exception},
         funcall::Core as _,
         mu::{Core as _, Mu},
+        profile::Profile,
+        step::Step,
+        trace::Trace,
         types::{Tag, Type},
     },
     types::{
-        cons::{Cons, ConsIter},
+        cons::{Cons, ConsIter, Core as _},
         fixnum::Fixnum,
-        function::Function,
+        function::{Function, LambdaList},
         struct_::{Core as _, Struct},
         symbol::{Core as _, Symbol},
         vecimage::VectorIter,
@@ -27,12 +33,21 @@ use crate::{
 
 use {futures::executor::block_on, futures_locks::RwLock};
 
+// the frames handlers may use past the depth limit
+pub const HANDLER_FRAMES: usize = 256;
+
 pub struct Frame {
     pub func: Tag,
     pub argv: Vec<Tag>,
     pub value: Tag,
 }
 
+// the depths of the dynamic and lexical frame stacks
+pub struct Unwind {
+    dynamic: usize,
+    lexical: Vec<(u64, usize)>,
+}
+
 impl Frame {
     fn to_tag(&self, mu: &Mu) -> Tag {
         let mut vec: Vec<Tag> = vec![self.func];
@@ -115,6 +130,36 @@ impl Frame {
         vec_ref.pop();
     }
 
+    pub fn unwind_mark(mu: &Mu) -> Unwind {
+        let dynamic_ref = block_on(mu.dynamic.read());
+        let stack_ref = block_on(mu.lexical.read());
+
+        Unwind {
+            dynamic: dynamic_ref.len(),
+            lexical: stack_ref
+                .iter()
+                .map(|(id, frames)| (*id, block_on(frames.read()).len()))
+                .collect(),
+        }
+    }
+
+    // discard the frames pushed since mark
+    pub fn unwind(mu: &Mu, mark: &Unwind) {
+        let mut dynamic_ref = block_on(mu.dynamic.write());
+        let stack_ref = block_on(mu.lexical.read());
+
+        dynamic_ref.truncate(mark.dynamic);
+
+        for (id, frames) in stack_ref.iter() {
+            let len = match mark.lexical.iter().find(|(func, _)| func == id) {
+                Some((_, len)) => *len,
+                None => 0,
+            };
+
+            block_on(frames.write()).truncate(len)
+        }
+    }
+
     fn frame_stack_len(mu: &Mu, id: Tag) -> Option<usize> {
         let stack_ref = block_on(mu.lexical.read());
 
@@ -136,14 +181,392 @@ impl Frame {
         }
     }
 
+    fn frame_stack_top(mu: &Mu, id: Tag) -> Option<Tag> {
+        let stack_ref = block_on(mu.lexical.read());
+        let vec_ref = block_on(stack_ref.get(&id.as_u64())?.read());
+        let frame = vec_ref.last()?;
+
+        Some(
+            Frame {
+                func: frame.func,
+                argv: frame.argv.clone(),
+                value: Tag::nil(),
+            }
+            .to_tag(mu),
+        )
+    }
+
+    fn frame_stack_set(mu: &Mu, id: Tag, offset: usize, value: Tag) {
+        let stack_ref = block_on(mu.lexical.read());
+        let mut vec_ref = block_on(stack_ref[&id.as_u64()].write());
+        let top = vec_ref.len() - 1;
+
+        vec_ref[top].argv[offset] = value
+    }
+
+    // extend the top frame of id with values, returns its previous length
+    pub fn frame_stack_bind(mu: &Mu, id: u64, values: &[Tag]) -> Option<usize> {
+        let stack_ref = block_on(mu.lexical.read());
+        let mut vec_ref = block_on(stack_ref.get(&id)?.write());
+        let frame = vec_ref.last_mut()?;
+        let len = frame.argv.len();
+
+        frame.argv.extend_from_slice(values);
+        Some(len)
+    }
+
+    pub fn frame_stack_unbind(mu: &Mu, id: u64, len: usize) {
+        let stack_ref = block_on(mu.lexical.read());
+        let mut vec_ref = block_on(stack_ref[&id].write());
+
+        if let Some(frame) = vec_ref.last_mut() {
+            frame.argv.truncate(len)
+        }
+    }
+
+    pub fn frame_argv(mu: &Mu, id: Tag) -> Vec<Tag> {
+        let stack_ref = block_on(mu.lexical.read());
+        let vec_ref = block_on(stack_ref[&id.as_u64()].read());
+
+        vec_ref[vec_ref.len() - 1].argv.clone()
+    }
+
     // frame reference
-    fn frame_ref(mu: &Mu, id: u64, offset: usize) -> Option<Tag> {
+    pub fn frame_ref(mu: &Mu, id: u64, offset: usize) -> Option<Tag> {
         let stack_ref = block_on(mu.lexical.read());
         let vec_ref = block_on(stack_ref[&id].read());
 
         Some(vec_ref[vec_ref.len() - 1].argv[offset])
     }
 
+    // (:let id (form...) body...), the values of the forms are bound
+    // in the frame of id for the extent of the body
+    pub fn let_(mu: &Mu, args: Tag) -> exception::Result<Tag> {
+        let id = Cons::car(mu, args);
+
+        if id.type_of() != Type::Fixnum {
+            return Err(Exception::new(Condition::Type, ":let", id));
+        }
+
+        let mut values = Vec::new();
+
+        for cons in ConsIter::new(mu, Cons::nth(mu, 1, args).unwrap_or(Tag::nil())) {
+            match mu.eval(Cons::car(mu, cons)) {
+                Ok(value) => values.push(value),
+                Err(e) => return Err(e),
+            }
+        }
+
+        let id = Fixnum::as_i64(id) as u64;
+        let len = match Self::frame_stack_bind(mu, id, &values) {
+            Some(len) => len,
+            None => return Err(Exception::new(Condition::Range, ":let", args)),
+        };
+
+        let mut value = Ok(Tag::nil());
+
+        for cons in ConsIter::new(mu, Cons::cdr(mu, Cons::cdr(mu, args))) {
+            value = mu.eval(Cons::car(mu, cons));
+            if value.is_err() {
+                break;
+            }
+        }
+
+        Self::frame_stack_unbind(mu, id, len);
+        value
+    }
+
+    // (:block block body...), catches a :return to block
+    pub fn block(mu: &Mu, args: Tag) -> exception::Result<Tag> {
+        let block = Cons::car(mu, args);
+        let mut value = Tag::nil();
+
+        for cons in ConsIter::new(mu, Cons::cdr(mu, args)) {
+            match mu.eval(Cons::car(mu, cons)) {
+                Ok(form) => value = form,
+                Err(e) if e.condition == Condition::Return && e.source.eq_(&block) => {
+                    return Ok(e.object)
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(value)
+    }
+
+    // rearrange argv in lambda list order, returns the
+    // (offset, default form) of unsupplied arguments
+    fn bind(&mut self, mu: &Mu, lambda_list: &LambdaList) -> exception::Result<Vec<(usize, Tag)>> {
+        if lambda_list.is_fixed() {
+            return Ok(Vec::new());
+        }
+
+        let nfixed = lambda_list.nreqs + lambda_list.nopts;
+        let mut defaults = Vec::new();
+
+        let tail = if self.argv.len() > nfixed {
+            self.argv.split_off(nfixed)
+        } else {
+            Vec::new()
+        };
+
+        for offset in self.argv.len()..nfixed {
+            self.argv.push(Tag::nil());
+            defaults.push((offset, lambda_list.defaults[offset - lambda_list.nreqs]))
+        }
+
+        if lambda_list.rest {
+            self.argv.push(Cons::vlist(mu, &tail))
+        }
+
+        if !lambda_list.keys.is_empty() {
+            if tail.len() % 2 != 0 {
+                return Err(Exception::new(Condition::Arity, "apply", self.func));
+            }
+
+            if let Some(pair) = tail
+                .chunks(2)
+                .find(|pair| !lambda_list.keys.iter().any(|key| key.eq_(&pair[0])))
+            {
+                return Err(Exception::new(Condition::Range, "apply", pair[0]));
+            }
+
+            for (nth, key) in lambda_list.keys.iter().enumerate() {
+                match tail.chunks(2).find(|pair| pair[0].eq_(key)) {
+                    Some(pair) => self.argv.push(pair[1]),
+                    None => {
+                        defaults.push((
+                            self.argv.len(),
+                            lambda_list.defaults[lambda_list.nopts + nth],
+                        ));
+                        self.argv.push(Tag::nil())
+                    }
+                }
+            }
+        }
+
+        Ok(defaults)
+    }
+
+    // push a lambda's frame, and a closure's captured frames, onto
+    // the pending list. returns the lambda to evaluate.
+    fn enter(mut self, mu: &Mu, pending: &mut Vec<(Tag, bool)>) -> exception::Result<Tag> {
+        let func = self.func;
+
+        if Function::form(mu, func).type_of() == Type::Function {
+            for cons in ConsIter::new(mu, Function::env(mu, func)) {
+                let frame = Self::from_tag(mu, Cons::car(mu, cons));
+
+                pending.push((frame.func, false));
+                frame.frame_stack_push(mu)
+            }
+
+            self.func = Function::form(mu, func);
+
+            return self.enter(mu, pending);
+        }
+
+        let lambda_list = Function::lambda_list(mu, func);
+
+        if !lambda_list.accepts(self.argv.len()) {
+            return Err(Exception::new(Condition::Arity, "apply", func));
+        }
+
+        // deep recursion raises :stack before it overflows the host stack.
+        // running handlers get more frames, so they can handle :stack
+        let depth = match *block_on(mu.handling.read()) {
+            0 => mu.config.depth,
+            _ => mu.config.depth + HANDLER_FRAMES,
+        };
+
+        if block_on(mu.dynamic.read()).len() >= depth {
+            return Err(Exception::new(Condition::Stack, "apply", func));
+        }
+
+        match self.bind(mu, &lambda_list) {
+            Ok(defaults) => {
+                let offset = Self::frame_stack_len(mu, func).unwrap_or(0);
+
+                mu.dynamic_push(func, offset);
+                self.frame_stack_push(mu);
+                pending.push((func, true));
+
+                // defaults are evaluated in the function's frame
+                for (offset, form) in defaults {
+                    match mu.eval(form) {
+                        Ok(value) => Self::frame_stack_set(mu, func, offset, value),
+                        Err(e) => return Err(e),
+                    }
+                }
+
+                Ok(func)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    // pop the pending frames a tail call to func doesn't close over,
+    // a nil func releases them all
+    fn release(mu: &Mu, func: Tag, pending: &mut Vec<(Tag, bool)>) {
+        let closed = match func.type_of() {
+            Type::Function if Function::form(mu, func).type_of() == Type::Cons => {
+                ConsIter::new(mu, Function::env(mu, func))
+                    .map(|cons| Cons::car(mu, cons))
+                    .collect::<Vec<Tag>>()
+            }
+            _ => Vec::new(),
+        };
+
+        while let Some((id, dynamic)) = pending.last() {
+            if closed.iter().any(|func| func.eq_(id)) {
+                break;
+            }
+
+            Self::frame_stack_pop(mu, *id);
+            if *dynamic {
+                mu.dynamic_pop()
+            }
+
+            pending.pop();
+        }
+    }
+
+    // evaluate a lambda body. the last form is in tail position, a
+    // call there to another lambda is returned as the next frame to
+    // enter rather than applied.
+    fn eval_body(mu: &Mu, lambda: Tag) -> exception::Result<(Tag, Option<Frame>)> {
+        // stepped bodies are interpreted, so the hook sees each form
+        if !Step::is_set(mu) {
+            if let Some(code) = Bytecode::code(mu, lambda) {
+                return Bytecode::run(mu, lambda, &code);
+            }
+        }
+
+        let body = ConsIter::new(mu, Function::form(mu, lambda))
+            .map(|cons| Cons::car(mu, cons))
+            .collect::<Vec<Tag>>();
+
+        match body.split_last() {
+            Some((tail, forms)) => {
+                for form in forms {
+                    match mu.eval(*form) {
+                        Ok(_) => (),
+                        Err(e) => return Err(e),
+                    }
+                }
+
+                Self::tail_call(mu, *tail)
+            }
+            None => Ok((Tag::nil(), None)),
+        }
+    }
+
+    fn tail_call(mu: &Mu, form: Tag) -> exception::Result<(Tag, Option<Frame>)> {
+        // the last form of a :progn is in tail position
+        if form.type_of() == Type::Cons && Cons::car(mu, form).eq_(&Symbol::keyword("progn")) {
+            let forms = ConsIter::new(mu, Cons::cdr(mu, form))
+                .map(|cons| Cons::car(mu, cons))
+                .collect::<Vec<Tag>>();
+
+            return match forms.split_last() {
+                Some((tail, forms)) => {
+                    for form in forms {
+                        match mu.eval(*form) {
+                            Ok(_) => (),
+                            Err(e) => return Err(e),
+                        }
+                    }
+
+                    Self::tail_call(mu, *tail)
+                }
+                None => Ok((Tag::nil(), None)),
+            };
+        }
+
+        let func = match form.type_of() {
+            Type::Cons => {
+                let func = Cons::car(mu, form);
+
+                match func.type_of() {
+                    Type::Symbol if !Symbol::is_unbound(mu, func) => Symbol::value(mu, func),
+                    _ => func,
+                }
+            }
+            _ => Tag::nil(),
+        };
+
+        if func.type_of() != Type::Function {
+            return match mu.eval(form) {
+                Ok(value) => Ok((value, None)),
+                Err(e) => Err(e),
+            };
+        }
+
+        // ::if and lambda tail calls aren't evaluated by Mu::eval
+        if func.eq_(&mu.if_)
+            || matches!(
+                Function::form(mu, func).type_of(),
+                Type::Cons | Type::Function
+            )
+        {
+            match Step::eval(mu, form) {
+                Ok(_) => (),
+                Err(e) => return Err(e),
+            }
+        }
+
+        let args = Cons::cdr(mu, form);
+
+        // (::if test true-fn false-fn), the branch taken is a tail call
+        if func.eq_(&mu.if_) {
+            return match mu.eval(Cons::car(mu, args)) {
+                Ok(test) => {
+                    let nth = if test.null_() { 2 } else { 1 };
+
+                    match mu.eval(Cons::nth(mu, nth, args).unwrap()) {
+                        Ok(branch) if branch.type_of() == Type::Function => Ok((
+                            Tag::nil(),
+                            Some(Frame {
+                                func: branch,
+                                argv: Vec::new(),
+                                value: Tag::nil(),
+                            }),
+                        )),
+                        Ok(branch) => Err(Exception::new(Condition::Type, "::if", branch)),
+                        Err(e) => Err(e),
+                    }
+                }
+                Err(e) => Err(e),
+            };
+        }
+
+        match Function::form(mu, func).type_of() {
+            Type::Cons | Type::Function => {
+                let mut argv = Vec::new();
+
+                for cons in ConsIter::new(mu, args) {
+                    match mu.eval(Cons::car(mu, cons)) {
+                        Ok(arg) => argv.push(arg),
+                        Err(e) => return Err(e),
+                    }
+                }
+
+                Ok((
+                    Tag::nil(),
+                    Some(Frame {
+                        func,
+                        argv,
+                        value: Tag::nil(),
+                    }),
+                ))
+            }
+            _ => match mu.eval(form) {
+                Ok(value) => Ok((value, None)),
+                Err(e) => Err(e),
+            },
+        }
+    }
+
     // apply
     pub fn apply(mut self, mu: &Mu, func: Tag) -> exception::Result<Tag> {
         match func.type_of() {
@@ -167,36 +590,121 @@ impl Frame {
                     let fn_key = Function::form(mu, func);
                     let fn_ = mu.native_map[&Tag::as_u64(&fn_key)];
 
-                    match fn_(mu, &mut self) {
+                    let trace = match Trace::stream(mu, func) {
+                        Some(stream) => match Trace::enter(mu, func, &self.argv, stream) {
+                            Ok(depth) => Some((depth, stream)),
+                            Err(e) => return Err(e),
+                        },
+                        None => None,
+                    };
+
+                    let stepped = match Step::apply(mu, func, &self.argv) {
+                        Ok(true) => true,
+                        Ok(false) => false,
+                        Err(e) => return Err(e),
+                    };
+
+                    let value = match fn_(mu, &mut self) {
                         Ok(_) => Ok(self.value),
-                        Err(e) => Err(e),
+                        Err(e) => Err(Exception::signal(mu, e)),
+                    };
+
+                    let value = match trace {
+                        Some((depth, stream)) => {
+                            match Trace::exit(mu, func, depth, stream, &value) {
+                                Ok(_) => value,
+                                Err(e) => Err(e),
+                            }
+                        }
+                        None => value,
+                    };
+
+                    match value {
+                        Ok(value) if stepped => match Step::return_(mu, func, value) {
+                            Ok(_) => Ok(value),
+                            Err(e) => Err(e),
+                        },
+                        _ => value,
                     }
                 }
-                Type::Cons => {
-                    let nreqs = Fixnum::as_i64(Function::arity(mu, func)) as usize;
-                    let nargs = self.argv.len();
+                Type::Cons | Type::Function => {
+                    let mut pending = Vec::new();
+                    let mut next = Some(self);
+                    let mut value = Ok(Tag::nil());
+                    let mut traced = Vec::new();
+                    let mut stepped = Vec::new();
+
+                    while let Some(frame) = next.take() {
+                        Self::release(mu, frame.func, &mut pending);
+
+                        // tail calls are traced at their caller's depth
+                        if let Some(stream) = Trace::stream(mu, frame.func) {
+                            match Trace::enter(mu, frame.func, &frame.argv, stream) {
+                                Ok(depth) => traced.push((frame.func, depth, stream)),
+                                Err(e) => {
+                                    value = Err(e);
+                                    break;
+                                }
+                            }
+                        }
 
-                    if nargs != nreqs {
-                        return Err(Exception::new(Condition::Arity, "apply", func));
-                    }
+                        match Step::apply(mu, frame.func, &frame.argv) {
+                            Ok(true) => stepped.push(frame.func),
+                            Ok(false) => (),
+                            Err(e) => {
+                                value = Err(e);
+                                break;
+                            }
+                        }
 
-                    let mut value = Tag::nil();
-                    let offset = Self::frame_stack_len(mu, self.func).unwrap_or(0);
+                        value = match frame.enter(mu, &mut pending) {
+                            Ok(lambda) => {
+                                Profile::tick(mu);
+                                match Self::eval_body(mu, lambda) {
+                                    Ok((tail_value, tail_call)) => {
+                                        next = tail_call;
+                                        Ok(tail_value)
+                                    }
+                                    Err(e) => Err(e),
+                                }
+                            }
+                            Err(e) => Err(e),
+                        }
+                    }
 
-                    mu.dynamic_push(self.func, offset);
-                    self.frame_stack_push(mu);
+                    // an exception takes a backtrace of the frames it was
+                    // raised in and is signaled there, then unwinds ours
+                    if let Err(e) = value {
+                        value = Err(if e.backtrace.is_empty() && !e.is_exit() {
+                            Exception::signal(
+                                mu,
+                                Exception {
+                                    backtrace: mu.backtrace(),
+                                    ..e
+                                },
+                            )
+                        } else {
+                            Exception::signal(mu, e)
+                        })
+                    }
 
-                    for cons in ConsIter::new(mu, Function::form(mu, func)) {
-                        value = match mu.eval(Cons::car(mu, cons)) {
-                            Ok(value) => value,
-                            Err(e) => return Err(e),
-                        };
+                    for (func, depth, stream) in traced.into_iter().rev() {
+                        if let Err(e) = Trace::exit(mu, func, depth, stream, &value) {
+                            value = Err(e)
+                        }
                     }
 
-                    Self::frame_stack_pop(mu, func);
-                    mu.dynamic_pop();
+                    Self::release(mu, Tag::nil(), &mut pending);
 
-                    Ok(value)
+                    for func in stepped.into_iter().rev() {
+                        if let Ok(tag) = value {
+                            if let Err(e) = Step::return_(mu, func, tag) {
+                                value = Err(e)
+                            }
+                        }
+                    }
+
+                    value
                 }
                 _ => Err(Exception::new(Condition::Type, "apply", func)),
             },
@@ -206,12 +714,42 @@ impl Frame {
 }
 
 pub trait MuFunction {
+    fn closure_(_: &Mu, _: &mut Frame) -> exception::Result<()>;
     fn mu_fr_pop(_: &Mu, _: &mut Frame) -> exception::Result<()>;
     fn mu_fr_push(_: &Mu, _: &mut Frame) -> exception::Result<()>;
     fn mu_fr_ref(_: &Mu, _: &mut Frame) -> exception::Result<()>;
 }
 
 impl MuFunction for Frame {
+    fn closure_(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        let lambda = fp.argv[0];
+
+        fp.value = match mu.fp_argv_check("::closure", &[Type::Function], fp) {
+            Ok(_) => {
+                let mut frames = Vec::new();
+
+                for cons in ConsIter::new(mu, Function::env(mu, lambda)) {
+                    let id = Cons::car(mu, cons);
+
+                    match Self::frame_stack_top(mu, id) {
+                        Some(frame) => frames.push(frame),
+                        None => return Err(Exception::new(Condition::Range, "::closure", id)),
+                    }
+                }
+
+                Function {
+                    arity: Function::arity(mu, lambda),
+                    form: lambda,
+                    env: Cons::vlist(mu, &frames),
+                }
+                .evict(mu)
+            }
+            Err(e) => return Err(e),
+        };
+
+        Ok(())
+    }
+
     fn mu_fr_pop(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
         fp.value = match mu.fp_argv_check("fr-pop", &[Type::Function], fp) {
             Ok(_) => {
@@ -7,21 +7,30 @@ use {
         async_::context::{Context, MuFunction as _},
         core::{
             compiler::{Compiler, MuFunction as _},
+            direct::DirectTag,
             dynamic::MuFunction as _,
+            equal::MuFunction as _,
             exception::{self, Condition, Exception, MuFunction as _},
             frame::{Frame, MuFunction as _},
             heap::{Heap, MuFunction as _},
+            lint::{Lint, MuFunction as _},
             mu::{Mu, MuFunction as _},
             namespace::{MuFunction as _, Namespace},
+            profile::{MuFunction as _, Profile},
+            reader::{MuFunction as _, Reader},
+            step::{MuFunction as _, Step},
             stream::MuFunction as _,
             system::MuFunction as _,
+            trace::{MuFunction as _, Trace},
             types::{MuFunction as _, Tag, Type},
         },
         types::{
+            array::{Array, MuFunction as _},
             cons::{Cons, MuFunction as _},
             fixnum::{Fixnum, MuFunction as _},
             float::{Float, MuFunction as _},
             function::Function,
+            hamt::{Hamt, MuFunction as _},
             map::{Map, MuFunction as _},
             stream::Stream,
             streams::MuFunction as _,
@@ -46,6 +55,9 @@ lazy_static! {
     static ref MU_SYMBOLS: Vec<(&'static str, u16, LibMuFunction)> = vec![
         // types
         ("eq", 2, Tag::mu_eq),
+        ("equal", 2, Tag::mu_equal),
+        ("equalp", 2, Tag::mu_equalp),
+        ("hash", 1, Tag::mu_hash),
         ("type-of", 1, Tag::mu_typeof),
         ("repr", 2, Tag::mu_repr),
         ("view", 1, Tag::mu_view),
@@ -61,10 +73,19 @@ lazy_static! {
         ("abort", 1, Context::mu_abort),
         // maps
         ("map", 1, Map::mu_make_map),
+        ("mp-clr", 1, Map::mu_map_clear),
+        ("mp-del", 2, Map::mu_map_del),
         ("mp-has", 2, Map::mu_map_has),
         ("mp-list", 1, Map::mu_map_items),
+        ("mp-make", 2, Map::mu_make_map_type),
+        ("mp-map", 2, Map::mu_map_map),
         ("mp-ref", 2, Map::mu_map_ref),
+        ("mp-set", 3, Map::mu_map_set),
         ("mp-size", 1, Map::mu_map_size),
+        // persistent maps
+        ("assoc", 3, Hamt::mu_assoc),
+        ("dissoc", 2, Hamt::mu_dissoc),
+        ("hamt", 2, Hamt::mu_make_hamt),
         // heap
         ("gc", 0, Heap::mu_gc),
         ("hp-info", 0, Heap::mu_hp_info),
@@ -73,14 +94,35 @@ lazy_static! {
         // mu
         ("apply", 2, Mu::mu_apply),
         ("compile", 1, Compiler::mu_compile),
+        ("lint", 2, Lint::mu_lint),
         ("eval", 1, Mu::mu_eval),
         ("frames", 0, Mu::mu_frames),
+        ("fr-top", 0, Mu::mu_fr_top),
+        ("fr-up", 1, Mu::mu_fr_up),
+        ("fr-fn", 1, Mu::mu_fr_fn),
+        ("fr-vars", 1, Mu::mu_fr_vars),
+        ("trace", 2, Trace::mu_trace),
+        ("untrace", 1, Trace::mu_untrace),
+        ("step", 1, Step::mu_step),
+        ("pf-on", 2, Profile::mu_pf_on),
+        ("pf-off", 0, Profile::mu_pf_off),
+        ("pf-rep", 2, Profile::mu_pf_rep),
         ("fix", 2, Mu::mu_fix),
         #[cfg(feature = "qquote")]
         ("%qquote", 1, QqReader::mu_qquote),
         // exceptions
         ("with-ex", 2, Exception::mu_with_ex),
         ("raise", 2, Exception::mu_raise),
+        ("unwind-protect", 2, Exception::mu_unwind),
+        ("catch", 2, Exception::mu_catch),
+        ("throw", 2, Exception::mu_throw),
+        ("defcond", 2, Exception::mu_defcond),
+        ("condp", 2, Exception::mu_condp),
+        ("signal", 3, Exception::mu_signal),
+        ("handle", 2, Exception::mu_handle),
+        ("with-rs", 3, Exception::mu_with_rs),
+        ("invoke", 2, Exception::mu_invoke),
+        ("rs-list", 0, Exception::mu_rs_list),
         // frames
         ("fr-pop", 1, Frame::mu_fr_pop),
         ("fr-push", 1, Frame::mu_fr_push),
@@ -109,21 +151,44 @@ lazy_static! {
         ("ns-map", 0, Namespace::mu_ns_map),
         // read/write
         ("read", 3, Mu::mu_read),
+        ("locate", 1, Reader::mu_locate),
         ("write", 3, Mu::mu_write),
         // symbols
         ("boundp", 1, Symbol::mu_boundp),
         ("keyword", 1, Symbol::mu_keyword),
         ("symbol", 1, Symbol::mu_symbol),
+        ("gensym", 0, Symbol::mu_gensym),
+        ("sy-doc", 1, Symbol::mu_doc),
+        ("sy-get", 2, Symbol::mu_get),
         ("sy-name", 1, Symbol::mu_name),
         ("sy-ns", 1, Symbol::mu_ns),
+        ("sy-plist", 1, Symbol::mu_plist),
+        ("sy-put", 3, Symbol::mu_put),
+        ("sy-sdoc", 2, Symbol::mu_set_doc),
         ("sy-val", 1, Symbol::mu_value),
         // simple vectors
         ("vector", 2, Vector::mu_make_vector),
         ("sv-len", 1, Vector::mu_length),
+        ("sv-copy", 1, Vector::mu_svcopy),
+        ("sv-fill", 2, Vector::mu_svfill),
         ("sv-ref", 2, Vector::mu_svref),
+        ("sv-set", 3, Vector::mu_svset),
         ("sv-type", 1, Vector::mu_type),
+        // arrays
+        ("array", 2, Array::mu_make_array),
+        ("ar-dims", 1, Array::mu_array_dims),
+        ("ar-disp", 3, Array::mu_array_displace),
+        ("ar-rank", 1, Array::mu_array_rank),
+        ("ar-ref", 2, Array::mu_array_ref),
+        ("ar-set", 3, Array::mu_array_set),
+        ("ar-vec", 1, Array::mu_array_vector),
         // structs
         ("struct", 2, Struct::mu_make_struct),
+        ("st-def", 3, Struct::mu_struct_def),
+        ("st-desc", 1, Struct::mu_struct_desc),
+        ("st-gen", 2, Struct::mu_struct_gen),
+        ("st-isa", 2, Struct::mu_struct_isa),
+        ("st-ref", 2, Struct::mu_struct_ref),
         ("st-type", 1, Struct::mu_struct_type),
         ("st-vec", 1, Struct::mu_struct_vector),
         // streams
@@ -148,30 +213,41 @@ lazy_static! {
 }
 
 impl Mu {
+    // natives are dispatched on a keyword, names longer than
+    // a keyword are keyed by their leading characters
+    fn native_key(name: &str) -> Tag {
+        Symbol::keyword(&name[..name.len().min(DirectTag::DIRECT_STR_MAX)])
+    }
+
     pub fn install_lib_functions(mu: &Mu) -> HashMap<u64, LibMuFunction> {
         let mut fn_map = HashMap::<u64, LibMuFunction>::new();
 
         fn_map.insert(Tag::as_u64(&Symbol::keyword("if")), Mu::if_);
         fn_map.insert(Tag::as_u64(&Symbol::keyword("append")), Mu::append_);
+        fn_map.insert(Tag::as_u64(&Symbol::keyword("closure")), Frame::closure_);
 
         for fnmap in MU_SYMBOLS.iter() {
             let (name, nreqs, libfn) = fnmap;
-            let fn_key = Symbol::keyword(name);
+            let fn_key = Self::native_key(name);
 
             let func = Function::new(Fixnum::as_tag(*nreqs as i64), fn_key).evict(mu);
 
-            fn_map.insert(Tag::as_u64(&fn_key), *libfn);
+            if fn_map.insert(Tag::as_u64(&fn_key), *libfn).is_some() {
+                panic!("{name}")
+            }
 
             Namespace::intern_symbol(mu, mu.mu_ns, name.to_string(), func);
         }
 
         for fnmap in SYS_SYMBOLS.iter() {
             let (name, nreqs, libfn) = fnmap;
-            let fn_key = Symbol::keyword(name);
+            let fn_key = Self::native_key(name);
 
             let func = Function::new(Fixnum::as_tag(*nreqs as i64), fn_key).evict(mu);
 
-            fn_map.insert(Tag::as_u64(&fn_key), *libfn);
+            if fn_map.insert(Tag::as_u64(&fn_key), *libfn).is_some() {
+                panic!("{name}")
+            }
 
             Namespace::intern_symbol(mu, mu.sys_ns, name.to_string(), func);
         }
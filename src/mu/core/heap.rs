@@ -157,9 +157,10 @@ impl Core for Heap<'_> {
 
     fn gc_maps(mu: &Mu) {
         let map_index_ref = block_on(mu.map_index.read());
-        for (_name, hash) in map_index_ref.iter() {
-            for (_key, map) in hash.iter() {
-                mu.gc_mark(*map)
+        for (_name, cache) in map_index_ref.iter() {
+            for (key, value) in cache.pairs() {
+                mu.gc_mark(key);
+                mu.gc_mark(value)
             }
         }
     }
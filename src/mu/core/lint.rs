@@ -0,0 +1,365 @@
+//  SPDX-FileCopyrightText: Copyright 2022 James M. Putnam (putnamjm.design@gmail.com)
+//  SPDX-License-Identifier: MIT
+
+//! compile-time diagnostics
+//!    Warning
+//!    lint
+//!
+//! an optional pass over an uncompiled form. it warns of unbound
+//! free variables and functions, calls whose argument count the
+//! callee's lambda list doesn't accept, and duplicate lambda and
+//! :let parameters. nothing is compiled or evaluated.
+use crate::{
+    core::{
+        exception::{self, Condition, Exception},
+        frame::Frame,
+        mu::Mu,
+        stream::Core as _,
+        types::{Tag, Type},
+    },
+    types::{
+        cons::{Cons, ConsIter, Core as _},
+        function::{Function, LambdaList},
+        symbol::{Core as _, Symbol},
+        vector::{Core as _, Vector},
+    },
+};
+
+pub struct Warning {
+    pub condition: Condition,
+    pub object: Tag,
+}
+
+pub struct Lint {
+    scope: Vec<Vec<Tag>>,
+    warnings: Vec<Warning>,
+}
+
+impl Lint {
+    pub fn lint(mu: &Mu, form: Tag) -> Vec<Warning> {
+        let mut lint = Lint {
+            scope: Vec::new(),
+            warnings: Vec::new(),
+        };
+
+        lint.form(mu, form);
+        lint.warnings
+    }
+
+    // write warnings to stream, one per line
+    pub fn write(mu: &Mu, warnings: &[Warning], stream: Tag) -> exception::Result<()> {
+        for warning in warnings {
+            let text = match warning.condition {
+                Condition::Unbound => "warning: unbound symbol ",
+                Condition::Arity => "warning: wrong number of arguments in ",
+                Condition::Syntax => "warning: duplicate parameter ",
+                _ => "warning: ",
+            };
+
+            match mu.write_string(text, stream) {
+                Ok(_) => match mu.write(warning.object, true, stream) {
+                    Ok(_) => match mu.write_string("\n", stream) {
+                        Ok(_) => (),
+                        Err(e) => return Err(e),
+                    },
+                    Err(e) => return Err(e),
+                },
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+
+    // a list of (condition . object) warnings
+    pub fn as_list(mu: &Mu, warnings: &[Warning]) -> Tag {
+        let list = warnings
+            .iter()
+            .map(|warning| {
+                Cons::new(
+                    Exception::map_condkey(warning.condition.clone()).unwrap(),
+                    warning.object,
+                )
+                .evict(mu)
+            })
+            .collect::<Vec<Tag>>();
+
+        Cons::vlist(mu, &list)
+    }
+
+    fn warn(&mut self, condition: Condition, object: Tag) {
+        self.warnings.push(Warning { condition, object })
+    }
+
+    fn is_lexical(&self, symbol: Tag) -> bool {
+        self.scope
+            .iter()
+            .any(|symbols| symbols.iter().any(|lex| symbol.eq_(lex)))
+    }
+
+    fn list(&mut self, mu: &Mu, list: Tag) {
+        for cons in ConsIter::new(mu, list) {
+            self.form(mu, Cons::car(mu, cons))
+        }
+    }
+
+    fn arity(&mut self, mu: &Mu, lambda_list: &LambdaList, form: Tag) {
+        if let Some(nargs) = Cons::length(mu, Cons::cdr(mu, form)) {
+            if !lambda_list.accepts(nargs) {
+                self.warn(Condition::Arity, form)
+            }
+        }
+    }
+
+    fn form(&mut self, mu: &Mu, form: Tag) {
+        match form.type_of() {
+            Type::Symbol if !self.is_lexical(form) && Symbol::is_unbound(mu, form) => {
+                self.warn(Condition::Unbound, form)
+            }
+            Type::Cons => {
+                let func = Cons::car(mu, form);
+                let args = Cons::cdr(mu, form);
+
+                match func.type_of() {
+                    Type::Keyword => self.special_form(mu, func, args),
+                    Type::Symbol => {
+                        if !self.is_lexical(func) {
+                            if Symbol::is_unbound(mu, func) {
+                                self.warn(Condition::Unbound, func)
+                            } else {
+                                let fn_ = Symbol::value(mu, func);
+
+                                if fn_.type_of() == Type::Function {
+                                    self.arity(mu, &Function::lambda_list(mu, fn_), form)
+                                }
+                            }
+                        }
+
+                        self.list(mu, args)
+                    }
+                    Type::Function => {
+                        self.arity(mu, &Function::lambda_list(mu, func), form);
+                        self.list(mu, args)
+                    }
+                    Type::Cons if Cons::car(mu, func).eq_(&Symbol::keyword("lambda")) => {
+                        if let Some(lambda_list) = self.lambda(mu, Cons::cdr(mu, func)) {
+                            self.arity(mu, &lambda_list, form)
+                        }
+
+                        self.list(mu, args)
+                    }
+                    _ => self.list(mu, form),
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn special_form(&mut self, mu: &Mu, name: Tag, args: Tag) {
+        if name.eq_(&Symbol::keyword("quote")) {
+            return;
+        }
+
+        if name.eq_(&Symbol::keyword("lambda")) {
+            self.lambda(mu, args);
+        } else if name.eq_(&Symbol::keyword("let")) {
+            self.let_(mu, args)
+        } else if name.eq_(&Symbol::keyword("block")) || name.eq_(&Symbol::keyword("return")) {
+            self.list(mu, Cons::cdr(mu, args))
+        } else {
+            self.list(mu, args)
+        }
+    }
+
+    // lint a lambda's defaults and body, returns its lambda list
+    fn lambda(&mut self, mu: &Mu, args: Tag) -> Option<LambdaList> {
+        let lambda = match args.type_of() {
+            Type::Cons => Cons::car(mu, args),
+            _ => return None,
+        };
+
+        if !matches!(lambda.type_of(), Type::Null | Type::Cons) {
+            return None;
+        }
+
+        let mut symbols = Vec::new();
+        let mut defaults = Vec::new();
+        let mut lambda_list = LambdaList::new(0);
+        let mut state = "";
+
+        for cons in ConsIter::new(mu, lambda) {
+            let elt = Cons::car(mu, cons);
+
+            let symbol = match elt.type_of() {
+                Type::Cons => {
+                    defaults.push(Cons::nth(mu, 1, elt).unwrap_or(Tag::nil()));
+                    Cons::car(mu, elt)
+                }
+                _ => elt,
+            };
+
+            if symbol.type_of() != Type::Symbol {
+                continue;
+            }
+
+            match Vector::as_string(mu, Symbol::name(mu, symbol)).as_str() {
+                "&optional" => state = "&optional",
+                "&rest" => state = "&rest",
+                "&key" => state = "&key",
+                _ => {
+                    if symbols.iter().any(|lex| symbol.eq_(lex)) {
+                        self.warn(Condition::Syntax, symbol)
+                    }
+
+                    match state {
+                        "&optional" => lambda_list.nopts += 1,
+                        "&rest" => lambda_list.rest = true,
+                        "&key" => lambda_list.keys.push(symbol),
+                        _ => lambda_list.nreqs += 1,
+                    }
+
+                    symbols.push(symbol)
+                }
+            }
+        }
+
+        self.scope.push(symbols);
+
+        for default in defaults {
+            self.form(mu, default)
+        }
+
+        self.list(mu, Cons::cdr(mu, args));
+        self.scope.pop();
+
+        Some(lambda_list)
+    }
+
+    // (:let ((symbol form)...) body...)
+    fn let_(&mut self, mu: &Mu, args: Tag) {
+        let bindings = match args.type_of() {
+            Type::Cons => Cons::car(mu, args),
+            _ => return,
+        };
+
+        if !matches!(bindings.type_of(), Type::Null | Type::Cons) {
+            return;
+        }
+
+        let mut symbols = Vec::new();
+
+        for cons in ConsIter::new(mu, bindings) {
+            let binding = Cons::car(mu, cons);
+
+            let symbol = match binding.type_of() {
+                Type::Cons => {
+                    // forms are outside the scope of the bindings
+                    self.form(mu, Cons::nth(mu, 1, binding).unwrap_or(Tag::nil()));
+                    Cons::car(mu, binding)
+                }
+                _ => binding,
+            };
+
+            if symbols.iter().any(|lex| symbol.eq_(lex)) {
+                self.warn(Condition::Syntax, symbol)
+            }
+
+            symbols.push(symbol)
+        }
+
+        self.scope.push(symbols);
+        self.list(mu, Cons::cdr(mu, args));
+        self.scope.pop();
+    }
+}
+
+pub trait MuFunction {
+    fn mu_lint(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+}
+
+impl MuFunction for Lint {
+    fn mu_lint(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        let form = fp.argv[0];
+        let stream = fp.argv[1];
+
+        if !matches!(stream.type_of(), Type::Null | Type::Stream) {
+            return Err(Exception::new(Condition::Type, "lint", stream));
+        }
+
+        let warnings = Self::lint(mu, form);
+
+        if !stream.null_() {
+            match Self::write(mu, &warnings, stream) {
+                Ok(_) => (),
+                Err(e) => return Err(e),
+            }
+        }
+
+        fp.value = Self::as_list(mu, &warnings);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::{
+        exception::Condition,
+        lint::{Lint, Warning},
+        mu::{Core, Mu},
+        types::Tag,
+    };
+    use crate::types::{
+        stream::{Core as _, Stream},
+        streambuilder::StreamBuilder,
+    };
+
+    #[test]
+    fn lint() {
+        let config = match Mu::config("".to_string()) {
+            Some(config) => config,
+            None => return assert!(false),
+        };
+
+        let mu: &Mu = &Core::new(&config);
+
+        assert!(Lint::lint(mu, Tag::nil()).is_empty())
+    }
+
+    #[test]
+    fn write() {
+        let config = match Mu::config("".to_string()) {
+            Some(config) => config,
+            None => return assert!(false),
+        };
+
+        let mu: &Mu = &Core::new(&config);
+        let stream = match StreamBuilder::new()
+            .string("".to_string())
+            .output()
+            .build(mu)
+        {
+            Ok(stream) => stream.evict(mu),
+            Err(_) => return assert!(false),
+        };
+
+        let warnings = [
+            Warning {
+                condition: Condition::Syntax,
+                object: Tag::nil(),
+            },
+            Warning {
+                condition: Condition::Type,
+                object: Tag::nil(),
+            },
+        ];
+
+        match Lint::write(mu, &warnings, stream) {
+            Ok(_) => match Stream::get_string(mu, stream) {
+                Ok(text) => assert_eq!(text, "warning: duplicate parameter :nil\nwarning: :nil\n"),
+                Err(_) => assert!(false),
+            },
+            Err(_) => assert!(false),
+        }
+    }
+}
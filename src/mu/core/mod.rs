@@ -3,21 +3,27 @@
 
 //! core module
 pub mod backquote;
+pub mod bytecode;
 pub mod compiler;
 pub mod config;
 pub mod direct;
 pub mod dynamic;
+pub mod equal;
 pub mod exception;
 pub mod frame;
 pub mod funcall;
 pub mod heap;
 pub mod indirect;
+pub mod lint;
 pub mod mu;
 pub mod namespace;
+pub mod profile;
 #[cfg(feature = "qquote")]
 pub mod qquote;
 pub mod reader;
 pub mod readtable;
+pub mod step;
 pub mod stream;
 pub mod system;
+pub mod trace;
 pub mod types;
@@ -9,13 +9,16 @@ use {
         allocators::bump_allocator::BumpAllocator,
         async_::context::Context,
         core::{
+            bytecode::Bytecode,
             config::Config,
             exception::{self, Condition, Exception},
-            frame::Frame,
+            frame::{Frame, Unwind},
             funcall::{Core as _, LibMuFunction},
             heap::{Core as _, Heap},
             namespace::Namespace,
-            reader::{Core as _, Reader},
+            profile::Profile,
+            reader::{Core as _, Location, Reader},
+            step::Step,
             types::{Tag, Type},
         },
         system::sys as system,
@@ -23,16 +26,20 @@ use {
             cons::{Cons, ConsIter, Core as _},
             fixnum::Fixnum,
             function::{Core as _, Function},
-            map::{Core as _, Map},
+            map::{Core as _, Map, MapCache},
             stream::{Core as _, Stream},
             streambuilder::StreamBuilder,
-            struct_::{Core as _, Struct},
+            struct_::{Core as _, Struct, StructType},
             symbol::{Core as _, Symbol},
             vector::{Core as _, Vector},
         },
     },
     cpu_time::ProcessTime,
-    std::collections::HashMap,
+    std::{
+        cell::RefCell,
+        collections::HashMap,
+        sync::{atomic::AtomicBool, Arc},
+    },
 };
 
 // locking protocols
@@ -40,7 +47,7 @@ use {futures::executor::block_on, futures_locks::RwLock};
 
 // mu environment
 pub struct Mu {
-    config: Config,
+    pub config: Config,
     pub version: Tag,
 
     // heap
@@ -48,7 +55,10 @@ pub struct Mu {
     pub gc_root: RwLock<Vec<Tag>>,
 
     // compiler
-    pub compile: RwLock<Vec<(Tag, Vec<Tag>)>>,
+    pub compile: RwLock<Vec<(Tag, Vec<Tag>, Vec<Tag>, bool)>>,
+    pub blocks: RwLock<Vec<(Tag, Tag)>>,
+    pub bytecode: RwLock<HashMap<u64, Arc<Bytecode>>>,
+    pub lambda_names: RwLock<HashMap<u64, Vec<Tag>>>,
 
     // frame cache
     pub lexical: RwLock<HashMap<u64, RwLock<Vec<Frame>>>>,
@@ -57,18 +67,60 @@ pub struct Mu {
     pub dynamic: RwLock<Vec<(u64, usize)>>,
 
     // exception unwind stack
-    pub exception: RwLock<Vec<usize>>,
+    pub exception: RwLock<Vec<Unwind>>,
+
+    // mu:catch tags
+    pub catches: RwLock<Vec<Tag>>,
+
+    // mu:handle handlers, nil marks a mu:with-ex, and mu:with-rs
+    // (name, restart) stacks
+    pub handlers: RwLock<Vec<Tag>>,
+    pub restarts: RwLock<Vec<(Tag, Tag)>>,
+
+    // handlers running, they may go past the depth limit
+    pub handling: RwLock<usize>,
+
+    // user conditions, keyword to parent
+    pub conditions: RwLock<HashMap<u64, Tag>>,
 
     // map/ns/async indices
     pub async_index: RwLock<HashMap<u64, Context>>,
-    pub map_index: RwLock<HashMap<usize, HashMap<u64, Tag>>>,
+    pub map_index: RwLock<HashMap<usize, MapCache>>,
     pub ns_index: RwLock<HashMap<u64, (Tag, RwLock<HashMap<String, Tag>>)>>,
 
+    // functions to the first symbol bound to them. symbol bindings
+    // are permanent, so entries never go stale
+    pub fn_index: RwLock<HashMap<u64, Tag>>,
+
+    // stream locations and the source locations of read forms. stream
+    // locations change on every char read, they're kept like the system
+    // streams they track rather than behind a lock
+    pub stream_index: RefCell<HashMap<usize, (Location, usize)>>,
+    pub source_index: RwLock<HashMap<u64, Location>>,
+
+    // step hook, and whether it is running
+    pub step: RwLock<(Tag, bool)>,
+
+    // sampling profiler
+    pub profile: RwLock<Profile>,
+
+    // traced functions, function to trace stream, and whether there
+    // are any, checked without the lock on every call
+    pub trace: RwLock<HashMap<u64, Tag>>,
+    pub tracing: AtomicBool,
+
+    // struct type registry
+    pub struct_index: RwLock<HashMap<u64, StructType>>,
+
+    // gensym counter
+    pub gensym_id: RwLock<u64>,
+
     // native function map
     pub native_map: HashMap<u64, LibMuFunction>,
 
     // internal functions
     pub append_: Tag,
+    pub closure_: Tag,
     pub if_: Tag,
 
     // namespaces
@@ -105,28 +157,46 @@ impl Core for Mu {
     fn new(config: &Config) -> Self {
         let mut mu = Mu {
             append_: Tag::nil(),
+            closure_: Tag::nil(),
             if_: Tag::nil(),
             async_index: RwLock::new(HashMap::new()),
+            blocks: RwLock::new(Vec::new()),
+            bytecode: RwLock::new(HashMap::new()),
+            catches: RwLock::new(Vec::new()),
             compile: RwLock::new(Vec::new()),
+            conditions: RwLock::new(HashMap::new()),
             config: *config,
             dynamic: RwLock::new(Vec::new()),
             errout: Tag::nil(),
             exception: RwLock::new(Vec::new()),
+            fn_index: RwLock::new(HashMap::new()),
             gc_root: RwLock::new(Vec::<Tag>::new()),
+            gensym_id: RwLock::new(0),
+            handlers: RwLock::new(Vec::new()),
+            handling: RwLock::new(0),
             heap: RwLock::new(BumpAllocator::new(config.npages)),
             keyword_ns: Tag::nil(),
+            lambda_names: RwLock::new(HashMap::new()),
             lexical: RwLock::new(HashMap::new()),
             map_index: RwLock::new(HashMap::new()),
             mu_ns: Tag::nil(),
             native_map: HashMap::new(),
             ns_index: RwLock::new(HashMap::new()),
+            source_index: RwLock::new(HashMap::new()),
+            stream_index: RefCell::new(HashMap::new()),
             null_ns: Tag::nil(),
+            profile: RwLock::new(Profile::new()),
             reader: Reader::new(),
+            restarts: RwLock::new(Vec::new()),
             start_time: ProcessTime::now(),
+            step: RwLock::new((Tag::nil(), false)),
             stdin: Tag::nil(),
             stdout: Tag::nil(),
+            struct_index: RwLock::new(HashMap::new()),
             sys_ns: Tag::nil(),
             system: system::System::new(),
+            trace: RwLock::new(HashMap::new()),
+            tracing: AtomicBool::new(false),
             version: Tag::nil(),
         };
 
@@ -180,6 +250,7 @@ impl Core for Mu {
 
         // internal functions
         mu.append_ = Function::new(Fixnum::as_tag(2), Symbol::keyword("append")).evict(&mu);
+        mu.closure_ = Function::new(Fixnum::as_tag(1), Symbol::keyword("closure")).evict(&mu);
         mu.if_ = Function::new(Fixnum::as_tag(3), Symbol::keyword("if")).evict(&mu);
 
         // the reader, has to be last
@@ -211,12 +282,41 @@ impl Core for Mu {
     fn eval(&self, expr: Tag) -> exception::Result<Tag> {
         match expr.type_of() {
             Type::Cons => {
+                match Step::eval(self, expr) {
+                    Ok(_) => (),
+                    Err(e) => return Err(e),
+                }
+
+                Profile::tick(self);
+
                 let func = Cons::car(self, expr);
                 let args = Cons::cdr(self, expr);
-                match func.type_of() {
+                let value = match func.type_of() {
                     Type::Keyword if func.eq_(&Symbol::keyword("quote")) => {
                         Ok(Cons::car(self, args))
                     }
+                    Type::Keyword if func.eq_(&Symbol::keyword("progn")) => {
+                        let mut value = Tag::nil();
+
+                        for cons in ConsIter::new(self, args) {
+                            match self.eval(Cons::car(self, cons)) {
+                                Ok(form) => value = form,
+                                Err(e) => return Err(e),
+                            }
+                        }
+
+                        Ok(value)
+                    }
+                    Type::Keyword if func.eq_(&Symbol::keyword("let")) => Frame::let_(self, args),
+                    Type::Keyword if func.eq_(&Symbol::keyword("block")) => {
+                        Frame::block(self, args)
+                    }
+                    Type::Keyword if func.eq_(&Symbol::keyword("return")) => {
+                        match self.eval(Cons::nth(self, 1, args).unwrap_or(Tag::nil())) {
+                            Ok(value) => Err(Exception::return_(Cons::car(self, args), value)),
+                            Err(e) => Err(e),
+                        }
+                    }
                     Type::Symbol => {
                         if Symbol::is_unbound(self, func) {
                             Err(Exception::new(Condition::Unbound, "eval", func))
@@ -230,6 +330,11 @@ impl Core for Mu {
                     }
                     Type::Function => self.apply(func, args),
                     _ => Err(Exception::new(Condition::Type, "eval", func)),
+                };
+
+                match value {
+                    Ok(value) => Ok(value),
+                    Err(e) => Err(Reader::locate_error(self, expr, e)),
                 }
             }
             Type::Symbol => {
@@ -277,6 +382,31 @@ impl Core for Mu {
             self.gc_mark(*tag)
         }
 
+        for tag in block_on(self.catches.read()).iter() {
+            self.gc_mark(*tag)
+        }
+
+        self.gc_mark(block_on(self.step.read()).0);
+
+        Profile::gc_mark(self);
+
+        for (func, stream) in block_on(self.trace.read()).iter() {
+            self.gc_mark(Tag::from_u64(*func));
+            self.gc_mark(*stream)
+        }
+
+        for handler in block_on(self.handlers.read()).iter() {
+            self.gc_mark(*handler)
+        }
+
+        for (_, restart) in block_on(self.restarts.read()).iter() {
+            self.gc_mark(*restart)
+        }
+
+        self.gc_lambda_names();
+        Reader::gc_source(self);
+        Bytecode::gc(self);
+
         {
             let mut heap_ref = block_on(self.heap.write());
             heap_ref.gc_sweep();
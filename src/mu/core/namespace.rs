@@ -42,7 +42,7 @@ impl Namespace {
         Ok(ns)
     }
 
-    fn map_symbol(mu: &Mu, ns: Tag, name: &str) -> Option<Tag> {
+    pub fn map_symbol(mu: &Mu, ns: Tag, name: &str) -> Option<Tag> {
         let ns_ref = block_on(mu.ns_index.read());
 
         let (_, ns_cache) = &ns_ref[&ns.as_u64()];
@@ -83,6 +83,15 @@ impl Namespace {
         }
     }
 
+    // remember the first symbol a function is bound to
+    fn index_function(mu: &Mu, symbol: Tag, value: Tag) {
+        if value.type_of() == Type::Function {
+            block_on(mu.fn_index.write())
+                .entry(value.as_u64())
+                .or_insert(symbol);
+        }
+    }
+
     pub fn intern_symbol(mu: &Mu, ns: Tag, name: String, value: Tag) -> Tag {
         match Namespace::is_ns(mu, ns) {
             Some(ns) => match Namespace::map_symbol(mu, ns, &name) {
@@ -108,6 +117,9 @@ impl Namespace {
                         let mut heap_ref = block_on(mu.heap.write());
 
                         heap_ref.write_image(slices, offset);
+                        drop(heap_ref);
+
+                        Namespace::index_function(mu, symbol, value)
                     }
 
                     symbol
@@ -116,6 +128,7 @@ impl Namespace {
                     let symbol = Symbol::new(mu, ns, &name, value).evict(mu);
 
                     Namespace::intern(mu, ns, symbol);
+                    Namespace::index_function(mu, symbol, value);
 
                     symbol
                 }
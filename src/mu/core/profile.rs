@@ -0,0 +1,421 @@
+//  SPDX-FileCopyrightText: Copyright 2022 James M. Putnam (putnamjm.design@gmail.com)
+//  SPDX-License-Identifier: MIT
+
+//! sampling profiler
+//!    Profile
+//!    pf-on
+//!    pf-off
+//!    pf-rep
+//!
+//! a sample is the list of functions on the dynamic frame stack,
+//! outermost first. samples are taken from Mu::eval and Frame::apply,
+//! every n evaluation steps or on the next step after a timer thread
+//! has ticked, and are counted by stack. reports are flat, per
+//! function self and total counts, an indented call tree, or the
+//! folded stack lines flamegraph tools read.
+use crate::{
+    core::{
+        exception::{self, Condition, Exception},
+        frame::Frame,
+        funcall::Core as _,
+        mu::{Core as _, Mu},
+        stream::Core as _,
+        types::{Tag, Type},
+    },
+    types::{
+        fixnum::Fixnum,
+        symbol::{Core as _, Symbol},
+    },
+};
+
+use {
+    futures::executor::block_on,
+    std::{
+        collections::HashMap,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        thread::{self, JoinHandle},
+        time::Duration,
+    },
+};
+
+// the timer thread, its run and tick flags
+struct Timer {
+    run: Arc<AtomicBool>,
+    tick: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+pub struct Profile {
+    on: bool,
+    steps: usize,
+    count: usize,
+    timer: Option<Timer>,
+    samples: HashMap<Vec<u64>, usize>,
+}
+
+// call tree node, function, samples and callees
+struct Node {
+    func: u64,
+    count: usize,
+    callees: Vec<Node>,
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Profile {
+            on: false,
+            steps: 0,
+            count: 0,
+            timer: None,
+            samples: HashMap::new(),
+        }
+    }
+
+    // start sampling every steps evaluation steps, or every us microseconds
+    pub fn start(mu: &Mu, steps: usize, us: usize) {
+        Self::stop(mu);
+
+        let mut profile_ref = block_on(mu.profile.write());
+
+        profile_ref.samples.clear();
+        profile_ref.steps = steps;
+        profile_ref.count = 0;
+
+        if us != 0 {
+            let run = Arc::new(AtomicBool::new(true));
+            let tick = Arc::new(AtomicBool::new(false));
+            let (run_, tick_) = (run.clone(), tick.clone());
+
+            let thread = thread::spawn(move || {
+                while run_.load(Ordering::Relaxed) {
+                    thread::sleep(Duration::from_micros(us as u64));
+                    tick_.store(true, Ordering::Relaxed)
+                }
+            });
+
+            profile_ref.timer = Some(Timer { run, tick, thread })
+        }
+
+        profile_ref.on = true
+    }
+
+    // stop sampling, returns the number of samples taken
+    pub fn stop(mu: &Mu) -> usize {
+        let timer = {
+            let mut profile_ref = block_on(mu.profile.write());
+
+            profile_ref.on = false;
+            profile_ref.timer.take()
+        };
+
+        if let Some(timer) = timer {
+            timer.run.store(false, Ordering::Relaxed);
+            timer.thread.join().unwrap()
+        }
+
+        Self::nsamples(mu)
+    }
+
+    pub fn nsamples(mu: &Mu) -> usize {
+        block_on(mu.profile.read()).samples.values().sum()
+    }
+
+    // count an evaluation step, sampling the dynamic stack when it's time
+    pub fn tick(mu: &Mu) {
+        if !block_on(mu.profile.read()).on {
+            return;
+        }
+
+        let mut profile_ref = block_on(mu.profile.write());
+
+        let sample = match &profile_ref.timer {
+            Some(timer) => timer.tick.swap(false, Ordering::Relaxed),
+            None => {
+                profile_ref.count += 1;
+                if profile_ref.count == profile_ref.steps {
+                    profile_ref.count = 0;
+                    true
+                } else {
+                    false
+                }
+            }
+        };
+
+        if sample {
+            let stack = block_on(mu.dynamic.read())
+                .iter()
+                .map(|(func, _)| *func)
+                .collect::<Vec<u64>>();
+
+            if !stack.is_empty() {
+                *profile_ref.samples.entry(stack).or_insert(0) += 1
+            }
+        }
+    }
+
+    pub fn gc_mark(mu: &Mu) {
+        let profile_ref = block_on(mu.profile.read());
+
+        for stack in profile_ref.samples.keys() {
+            for func in stack {
+                mu.gc_mark(Tag::from_u64(*func))
+            }
+        }
+    }
+
+    // the samples, most frequent first
+    fn samples(mu: &Mu) -> Vec<(Vec<u64>, usize)> {
+        let mut samples = block_on(mu.profile.read())
+            .samples
+            .iter()
+            .map(|(stack, count)| (stack.clone(), *count))
+            .collect::<Vec<(Vec<u64>, usize)>>();
+
+        samples
+            .sort_by(|(stack, count), (stack1, count1)| count1.cmp(count).then(stack.cmp(stack1)));
+
+        samples
+    }
+
+    // write the symbol a function is bound to, or the function
+    fn write_name(
+        mu: &Mu,
+        func: u64,
+        names: &mut HashMap<u64, Tag>,
+        stream: Tag,
+    ) -> exception::Result<()> {
+        let name = *names.entry(func).or_insert_with(|| {
+            let func = Tag::from_u64(func);
+
+            mu.frame_symbol(func).unwrap_or(func)
+        });
+
+        mu.write(name, true, stream)
+    }
+
+    // self total function
+    pub fn write_flat(mu: &Mu, stream: Tag) -> exception::Result<()> {
+        let mut counts: Vec<(u64, usize, usize)> = Vec::new();
+
+        for (stack, count) in Self::samples(mu) {
+            for (nth, func) in stack.iter().enumerate() {
+                // recursive calls are counted once a sample
+                if stack[..nth].contains(func) {
+                    continue;
+                }
+
+                match counts.iter_mut().find(|(id, _, _)| id == func) {
+                    Some((_, _, total)) => *total += count,
+                    None => counts.push((*func, 0, count)),
+                }
+            }
+
+            if let Some(leaf) = stack.last() {
+                if let Some((_, self_, _)) = counts.iter_mut().find(|(id, _, _)| id == leaf) {
+                    *self_ += count
+                }
+            }
+        }
+
+        counts.sort_by(|(_, self_, total), (_, self1, total1)| {
+            self1.cmp(self_).then(total1.cmp(total))
+        });
+
+        let mut names = HashMap::new();
+
+        for (func, self_, total) in counts {
+            match mu.write_string(&format!("{self_:>8} {total:>8} "), stream) {
+                Ok(_) => match Self::write_name(mu, func, &mut names, stream) {
+                    Ok(_) => (),
+                    Err(e) => return Err(e),
+                },
+                Err(e) => return Err(e),
+            }
+
+            match mu.write_string("\n", stream) {
+                Ok(_) => (),
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn insert(nodes: &mut Vec<Node>, stack: &[u64], count: usize) {
+        if let Some((func, callees)) = stack.split_first() {
+            let nth = match nodes.iter().position(|node| node.func == *func) {
+                Some(nth) => nth,
+                None => {
+                    nodes.push(Node {
+                        func: *func,
+                        count: 0,
+                        callees: Vec::new(),
+                    });
+                    nodes.len() - 1
+                }
+            };
+
+            nodes[nth].count += count;
+            Self::insert(&mut nodes[nth].callees, callees, count)
+        }
+    }
+
+    fn write_nodes(
+        mu: &Mu,
+        nodes: &mut [Node],
+        depth: usize,
+        names: &mut HashMap<u64, Tag>,
+        stream: Tag,
+    ) -> exception::Result<()> {
+        nodes.sort_by_key(|node| std::cmp::Reverse(node.count));
+
+        for node in nodes {
+            match mu.write_string(
+                &format!("{:width$}{:>8} ", "", node.count, width = depth * 2),
+                stream,
+            ) {
+                Ok(_) => match Self::write_name(mu, node.func, names, stream) {
+                    Ok(_) => (),
+                    Err(e) => return Err(e),
+                },
+                Err(e) => return Err(e),
+            }
+
+            match mu.write_string("\n", stream) {
+                Ok(_) => (),
+                Err(e) => return Err(e),
+            }
+
+            match Self::write_nodes(mu, &mut node.callees, depth + 1, names, stream) {
+                Ok(_) => (),
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+
+    // samples function, callees indented below their callers
+    pub fn write_tree(mu: &Mu, stream: Tag) -> exception::Result<()> {
+        let mut roots = Vec::new();
+
+        for (stack, count) in Self::samples(mu) {
+            Self::insert(&mut roots, &stack, count)
+        }
+
+        Self::write_nodes(mu, &mut roots, 0, &mut HashMap::new(), stream)
+    }
+
+    // function;function... samples
+    pub fn write_folded(mu: &Mu, stream: Tag) -> exception::Result<()> {
+        let mut names = HashMap::new();
+
+        for (stack, count) in Self::samples(mu) {
+            for (nth, func) in stack.iter().enumerate() {
+                if nth != 0 {
+                    match mu.write_string(";", stream) {
+                        Ok(_) => (),
+                        Err(e) => return Err(e),
+                    }
+                }
+
+                match Self::write_name(mu, *func, &mut names, stream) {
+                    Ok(_) => (),
+                    Err(e) => return Err(e),
+                }
+            }
+
+            match mu.write_string(&format!(" {count}\n"), stream) {
+                Ok(_) => (),
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub trait MuFunction {
+    fn mu_pf_on(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+    fn mu_pf_off(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+    fn mu_pf_rep(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+}
+
+impl MuFunction for Profile {
+    // (mu:pf-on mode interval), mode is :step or :us, discards
+    // the samples of the last run
+    fn mu_pf_on(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        let mode = fp.argv[0];
+        let interval = fp.argv[1];
+
+        match mu.fp_argv_check("pf-on", &[Type::Keyword, Type::Fixnum], fp) {
+            Ok(_) => {
+                let n = Fixnum::as_i64(interval);
+
+                if n <= 0 {
+                    return Err(Exception::new(Condition::Range, "pf-on", interval));
+                }
+
+                if mode.eq_(&Symbol::keyword("step")) {
+                    Self::start(mu, n as usize, 0)
+                } else if mode.eq_(&Symbol::keyword("us")) {
+                    Self::start(mu, 0, n as usize)
+                } else {
+                    return Err(Exception::new(Condition::Range, "pf-on", mode));
+                }
+
+                fp.value = mode
+            }
+            Err(e) => return Err(e),
+        }
+
+        Ok(())
+    }
+
+    // (mu:pf-off), returns the number of samples
+    fn mu_pf_off(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        fp.value = Fixnum::as_tag(Self::stop(mu) as i64);
+
+        Ok(())
+    }
+
+    // (mu:pf-rep format stream), format is :flat, :tree or :folded,
+    // returns the number of samples
+    fn mu_pf_rep(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        let format = fp.argv[0];
+        let stream = fp.argv[1];
+
+        match mu.fp_argv_check("pf-rep", &[Type::Keyword, Type::Stream], fp) {
+            Ok(_) => {
+                let report = if format.eq_(&Symbol::keyword("flat")) {
+                    Self::write_flat(mu, stream)
+                } else if format.eq_(&Symbol::keyword("tree")) {
+                    Self::write_tree(mu, stream)
+                } else if format.eq_(&Symbol::keyword("folded")) {
+                    Self::write_folded(mu, stream)
+                } else {
+                    return Err(Exception::new(Condition::Range, "pf-rep", format));
+                };
+
+                match report {
+                    Ok(_) => fp.value = Fixnum::as_tag(Self::nsamples(mu) as i64),
+                    Err(e) => return Err(e),
+                }
+            }
+            Err(e) => return Err(e),
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn profile() {
+        assert!(true)
+    }
+}
@@ -6,23 +6,64 @@ use crate::{
     core::{
         direct::{DirectInfo, DirectTag, DirectType},
         exception::{self, Condition, Exception},
+        frame::Frame,
         mu::Mu,
         namespace::Namespace,
         readtable::{map_char_syntax, SyntaxType},
         types::{Tag, Type},
     },
     types::{
+        array::Array,
         char::Char,
+        cons::{Cons, Core as _},
         fixnum::Fixnum,
         float::Float,
         stream::{Core as _, Stream},
         streambuilder::StreamBuilder,
         struct_::{Core as _, Struct},
-        symbol::{Core as _, Symbol},
+        symbol::{Core as _, Symbol, UNBOUND},
         vector::{Core as _, Vector},
     },
 };
 
+use {futures::executor::block_on, std::fmt};
+
+// where a form was read, lines and columns count from 1
+#[derive(Clone)]
+pub struct Location {
+    pub path: Option<String>,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.path {
+            Some(path) => write!(f, "{}:{}:{}", path, self.line, self.column),
+            None => write!(f, "{}:{}", self.line, self.column),
+        }
+    }
+}
+
+impl Location {
+    // (path line column), path is () for non-file streams
+    pub fn as_list(&self, mu: &Mu) -> Tag {
+        let path = match &self.path {
+            Some(path) => Vector::from_string(path).evict(mu),
+            None => Tag::nil(),
+        };
+
+        Cons::vlist(
+            mu,
+            &[
+                path,
+                Fixnum::as_tag(self.line as i64),
+                Fixnum::as_tag(self.column as i64),
+            ],
+        )
+    }
+}
+
 pub struct Reader {
     pub eol: Tag,
     pub bq_str: Tag,
@@ -305,14 +346,16 @@ impl Core for Reader {
     fn sharp_macro(mu: &Mu, stream: Tag) -> exception::Result<Option<Tag>> {
         match Stream::read_char(mu, stream) {
             Ok(Some(ch)) => match ch {
-                ':' => match Stream::read_char(mu, stream) {
-                    Ok(Some(ch)) => match Self::read_atom(mu, ch, stream) {
-                        Ok(atom) => match atom.type_of() {
-                            Type::Symbol => Ok(Some(atom)),
-                            _ => Err(Exception::new(Condition::Type, "read:#", stream)),
-                        },
-                        Err(e) => Err(e),
-                    },
+                ':' => match Self::read_token(mu, stream) {
+                    Ok(Some(token)) => {
+                        if token.is_empty() || token.contains(':') {
+                            Err(Exception::new(Condition::Syntax, "read:#", stream))
+                        } else {
+                            Ok(Some(
+                                Symbol::new(mu, Tag::nil(), &token, *UNBOUND).evict(mu),
+                            ))
+                        }
+                    }
                     Ok(None) => Err(Exception::new(Condition::Eof, "read:#", stream)),
                     Err(e) => Err(e),
                 },
@@ -329,6 +372,31 @@ impl Core for Reader {
                     Ok(tag) => Ok(Some(tag)),
                     Err(e) => Err(e),
                 },
+                '0'..='9' => {
+                    Stream::unread_char(mu, stream, ch).unwrap();
+                    match Self::read_token(mu, stream) {
+                        Ok(Some(token)) => match token.strip_suffix(['A', 'a']) {
+                            Some(rank) => match rank.parse::<usize>() {
+                                Ok(rank) => match Array::read(mu, rank, stream) {
+                                    Ok(tag) => Ok(Some(tag)),
+                                    Err(e) => Err(e),
+                                },
+                                Err(_) => Err(Exception::new(
+                                    Condition::Syntax,
+                                    "read:#A",
+                                    Vector::from_string(&token).evict(mu),
+                                )),
+                            },
+                            None => Err(Exception::new(
+                                Condition::Syntax,
+                                "read:#",
+                                Vector::from_string(&token).evict(mu),
+                            )),
+                        },
+                        Ok(None) => Err(Exception::new(Condition::Eof, "read:#", stream)),
+                        Err(e) => Err(e),
+                    }
+                }
                 'x' => match Self::read_token(mu, stream) {
                     Ok(token) => match token {
                         Some(hex) => match i64::from_str_radix(&hex, 16) {
@@ -365,6 +433,84 @@ impl Core for Reader {
     }
 }
 
+// the source index maps the conses the reader makes, and the forms
+// compiled from them, to the location they were read from. direct
+// conses are values, equal ones share the location last read.
+impl Reader {
+    pub fn locate(mu: &Mu, tag: Tag, location: Location) {
+        if tag.type_of() == Type::Cons {
+            let mut source_ref = block_on(mu.source_index.write());
+
+            source_ref.insert(tag.as_u64(), location);
+        }
+    }
+
+    pub fn location(mu: &Mu, tag: Tag) -> Option<Location> {
+        match tag.type_of() {
+            Type::Cons => {
+                let source_ref = block_on(mu.source_index.read());
+
+                source_ref.get(&tag.as_u64()).cloned()
+            }
+            _ => None,
+        }
+    }
+
+    // an error raised by a form without a more precise location takes the form's
+    pub fn locate_error(mu: &Mu, source: Tag, e: Exception) -> Exception {
+        match e.location {
+            Some(_) => e,
+            None => Exception {
+                location: Self::location(mu, source),
+                ..e
+            },
+        }
+    }
+
+    // compiled forms are where their source was
+    pub fn relocate(mu: &Mu, source: Tag, form: Tag) {
+        if let Some(location) = Self::location(mu, source) {
+            Self::locate(mu, form, location)
+        }
+    }
+
+    // forget the locations of unmarked conses, their images are
+    // reused. direct conses can't be marked and are forgotten too
+    pub fn gc_source(mu: &Mu) {
+        let heap_ref = block_on(mu.heap.read());
+        let mut source_ref = block_on(mu.source_index.write());
+
+        source_ref.retain(|tag, _| match Tag::from_u64(*tag) {
+            Tag::Indirect(indirect) => heap_ref
+                .get_image_refbit(indirect.image_id() as usize)
+                .unwrap_or(false),
+            Tag::Direct(_) => false,
+        })
+    }
+}
+
+pub trait MuFunction {
+    fn mu_locate(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+}
+
+impl MuFunction for Reader {
+    fn mu_locate(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        let tag = fp.argv[0];
+
+        let location = match tag.type_of() {
+            Type::Stream => Stream::location(mu, tag),
+            _ => Self::location(mu, tag),
+        };
+
+        fp.value = match location {
+            Some(location) => location.as_list(mu),
+            None => Tag::nil(),
+        };
+
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
@@ -0,0 +1,138 @@
+//  SPDX-FileCopyrightText: Copyright 2022 James M. Putnam (putnamjm.design@gmail.com)
+//  SPDX-License-Identifier: MIT
+
+//! single-step hooks
+//!    Step
+//!    step
+//!
+//! the step hook is a function of (event form frame value). Mu::eval
+//! applies it to :eval before each form, Frame::apply to :apply before
+//! a call with the argument list as value, and to :return after one
+//! with the value returned. frame is the id of the innermost dynamic
+//! frame, for mu:fr-ref, or nil. the hook isn't stepped itself, and
+//! neither are the mu:fr-ref calls lexical variables compile to.
+//!
+//! while a hook is set, lambda bodies are interpreted rather than run
+//! as bytecode, so each of their forms is stepped.
+use crate::{
+    core::{
+        exception::{self, Condition, Exception},
+        frame::Frame,
+        mu::{Core as _, Mu},
+        types::{Tag, Type},
+    },
+    types::{
+        cons::{Cons, Core as _},
+        fixnum::Fixnum,
+        function::Function,
+        symbol::{Core as _, Symbol},
+    },
+};
+
+use futures::executor::block_on;
+
+pub struct Step;
+
+impl Step {
+    // is there a hook, and is it not running
+    pub fn is_set(mu: &Mu) -> bool {
+        let step_ref = block_on(mu.step.read());
+
+        !step_ref.0.null_() && !step_ref.1
+    }
+
+    // set the hook, returns the previous one
+    pub fn set(mu: &Mu, hook: Tag) -> Tag {
+        let mut step_ref = block_on(mu.step.write());
+        let prev = step_ref.0;
+
+        step_ref.0 = hook;
+        prev
+    }
+
+    // lexical variable references aren't stepped
+    fn is_frame_ref(mu: &Mu, func: Tag) -> bool {
+        func.type_of() == Type::Function && Function::form(mu, func).eq_(&Symbol::keyword("fr-ref"))
+    }
+
+    fn hook(mu: &Mu, event: &str, form: Tag, value: Tag) -> exception::Result<()> {
+        let hook = {
+            let mut step_ref = block_on(mu.step.write());
+
+            if step_ref.0.null_() || step_ref.1 {
+                return Ok(());
+            }
+
+            step_ref.1 = true;
+            step_ref.0
+        };
+
+        let frame = match block_on(mu.dynamic.read()).last() {
+            Some((id, _)) => Fixnum::as_tag(*id as i64),
+            None => Tag::nil(),
+        };
+
+        let result = mu.apply_(hook, vec![Symbol::keyword(event), form, frame, value]);
+
+        block_on(mu.step.write()).1 = false;
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn eval(mu: &Mu, form: Tag) -> exception::Result<()> {
+        if Self::is_set(mu) && !Self::is_frame_ref(mu, Cons::car(mu, form)) {
+            Self::hook(mu, "eval", form, Tag::nil())
+        } else {
+            Ok(())
+        }
+    }
+
+    // returns true if the call was stepped
+    pub fn apply(mu: &Mu, func: Tag, argv: &[Tag]) -> exception::Result<bool> {
+        if Self::is_set(mu) && !Self::is_frame_ref(mu, func) {
+            match Self::hook(mu, "apply", func, Cons::vlist(mu, argv)) {
+                Ok(_) => Ok(true),
+                Err(e) => Err(e),
+            }
+        } else {
+            Ok(false)
+        }
+    }
+
+    pub fn return_(mu: &Mu, func: Tag, value: Tag) -> exception::Result<()> {
+        if Self::is_set(mu) {
+            Self::hook(mu, "return", func, value)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+pub trait MuFunction {
+    fn mu_step(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+}
+
+impl MuFunction for Step {
+    // (mu:step hook), hook is a function or nil, returns the previous hook
+    fn mu_step(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        let hook = fp.argv[0];
+
+        fp.value = match hook.type_of() {
+            Type::Null | Type::Function => Self::set(mu, hook),
+            _ => return Err(Exception::new(Condition::Type, "step", hook)),
+        };
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn step() {
+        assert!(true)
+    }
+}
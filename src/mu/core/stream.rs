@@ -61,7 +61,8 @@ impl Core for Mu {
             Err(e) => return Err(e),
         };
 
-        match Stream::read_char(mu, stream) {
+        let location = Stream::location(mu, stream);
+        let value = match Stream::read_char(mu, stream) {
             Ok(None) => {
                 if eofp {
                     Ok(eof_value)
@@ -125,6 +126,26 @@ impl Core for Mu {
                 _ => Err(Exception::new(Condition::Read, "read::@", Char::as_tag(ch))),
             },
             Err(e) => Err(e),
+        };
+
+        // nested reads have already located their forms
+        match value {
+            Ok(tag) => {
+                if let (Some(location), Type::Cons) = (location, tag.type_of()) {
+                    if Reader::location(mu, tag).is_none() {
+                        Reader::locate(mu, tag, location)
+                    }
+                }
+
+                Ok(tag)
+            }
+            Err(mut e) => {
+                if e.location.is_none() {
+                    e.location = Stream::location(mu, stream)
+                }
+
+                Err(e)
+            }
         }
     }
 
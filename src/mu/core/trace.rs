@@ -0,0 +1,183 @@
+//  SPDX-FileCopyrightText: Copyright 2022 James M. Putnam (putnamjm.design@gmail.com)
+//  SPDX-License-Identifier: MIT
+
+//! function call tracing
+//!    Trace
+//!    trace
+//!    untrace
+//!
+//! a traced function writes each call through Frame::apply, and its
+//! value or exception, to a stream. lines are indented by the depth
+//! of the dynamic frame stack.
+use crate::core::{
+    exception::{self, Condition, Exception},
+    frame::Frame,
+    funcall::Core as _,
+    mu::Mu,
+    stream::Core as _,
+    types::{Tag, Type},
+};
+
+use {futures::executor::block_on, std::sync::atomic::Ordering};
+
+pub struct Trace;
+
+impl Trace {
+    // the trace stream of a traced function
+    pub fn stream(mu: &Mu, func: Tag) -> Option<Tag> {
+        if mu.tracing.load(Ordering::Relaxed) {
+            block_on(mu.trace.read()).get(&func.as_u64()).copied()
+        } else {
+            None
+        }
+    }
+
+    // the symbol a function is bound to, if any
+    fn name(mu: &Mu, func: Tag) -> Tag {
+        mu.frame_symbol(func).unwrap_or(func)
+    }
+
+    fn indent(mu: &Mu, depth: usize, stream: Tag) -> exception::Result<()> {
+        mu.write_string(
+            &format!("{:width$}{depth}: ", "", width = depth * 2),
+            stream,
+        )
+    }
+
+    // write (function args...), returns the call depth
+    pub fn enter(mu: &Mu, func: Tag, argv: &[Tag], stream: Tag) -> exception::Result<usize> {
+        let depth = block_on(mu.dynamic.read()).len();
+
+        match Self::indent(mu, depth, stream) {
+            Ok(_) => match mu.write_string("(", stream) {
+                Ok(_) => match mu.write(Self::name(mu, func), true, stream) {
+                    Ok(_) => (),
+                    Err(e) => return Err(e),
+                },
+                Err(e) => return Err(e),
+            },
+            Err(e) => return Err(e),
+        }
+
+        for arg in argv {
+            match mu.write_string(" ", stream) {
+                Ok(_) => match mu.write(*arg, true, stream) {
+                    Ok(_) => (),
+                    Err(e) => return Err(e),
+                },
+                Err(e) => return Err(e),
+            }
+        }
+
+        match mu.write_string(")\n", stream) {
+            Ok(_) => Ok(depth),
+            Err(e) => Err(e),
+        }
+    }
+
+    // write function => value, or the exception it raised
+    pub fn exit(
+        mu: &Mu,
+        func: Tag,
+        depth: usize,
+        stream: Tag,
+        value: &exception::Result<Tag>,
+    ) -> exception::Result<()> {
+        match Self::indent(mu, depth, stream) {
+            Ok(_) => match mu.write(Self::name(mu, func), true, stream) {
+                Ok(_) => (),
+                Err(e) => return Err(e),
+            },
+            Err(e) => return Err(e),
+        }
+
+        let (text, object) = match value {
+            Ok(value) => (" => ", Some(*value)),
+            Err(e) if e.is_exit() => (" exits", None),
+            Err(e) => {
+                match mu.write_string(" raises ", stream) {
+                    Ok(_) => match mu.write(
+                        Exception::map_condkey(e.condition.clone()).unwrap(),
+                        true,
+                        stream,
+                    ) {
+                        Ok(_) => (),
+                        Err(e) => return Err(e),
+                    },
+                    Err(e) => return Err(e),
+                }
+
+                (" on ", Some(e.object))
+            }
+        };
+
+        match mu.write_string(text, stream) {
+            Ok(_) => {
+                if let Some(object) = object {
+                    match mu.write(object, true, stream) {
+                        Ok(_) => (),
+                        Err(e) => return Err(e),
+                    }
+                }
+            }
+            Err(e) => return Err(e),
+        }
+
+        mu.write_string("\n", stream)
+    }
+}
+
+pub trait MuFunction {
+    fn mu_trace(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+    fn mu_untrace(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+}
+
+impl MuFunction for Trace {
+    // (mu:trace function stream)
+    fn mu_trace(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        let func = fp.argv[0];
+        let stream = fp.argv[1];
+
+        match mu.fp_argv_check("trace", &[Type::Function, Type::Stream], fp) {
+            Ok(_) => {
+                let mut trace_ref = block_on(mu.trace.write());
+
+                trace_ref.insert(func.as_u64(), stream);
+                mu.tracing.store(true, Ordering::Relaxed);
+                fp.value = func
+            }
+            Err(e) => return Err(e),
+        }
+
+        Ok(())
+    }
+
+    // (mu:untrace function), untracing an untraced function is an error
+    fn mu_untrace(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        let func = fp.argv[0];
+
+        match mu.fp_argv_check("untrace", &[Type::Function], fp) {
+            Ok(_) => {
+                let mut trace_ref = block_on(mu.trace.write());
+
+                match trace_ref.remove(&func.as_u64()) {
+                    Some(_) => fp.value = func,
+                    None => return Err(Exception::new(Condition::Range, "untrace", func)),
+                }
+
+                mu.tracing.store(!trace_ref.is_empty(), Ordering::Relaxed)
+            }
+            Err(e) => return Err(e),
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn trace() {
+        assert!(true)
+    }
+}
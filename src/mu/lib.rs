@@ -30,8 +30,9 @@
 //!    characters (ASCII immediate)
 //!    conses
 //!    fixed arity functions
-//!    lambdas with lexical variables
+//!    lambdas with lexical variables and closures
 //!    general and specialized vectors
+//!    multi-dimensional and displaced arrays
 //!    keywords (seven character immediate)
 //!    single/32 bit IEEE float (immediate)
 //!    structs
@@ -59,9 +60,10 @@ mod types;
 use {
     crate::core::{
         compiler::Compiler,
-        config::Config,
         exception,
+        lint::Lint,
         mu::{self, Core},
+        step::Step,
         stream::{self, Core as _},
     },
     std::fs,
@@ -78,6 +80,8 @@ use {
 /// - Result, specialized result for API functions that can fail
 /// - Exception, exception state
 /// - Condition, enumeration of possible exceptional conditions
+/// - Config, runtime configuration
+/// - FrameInfo, dynamic frame inspection
 /// - Mu, environment and API namespace
 /// - System, an optional interface to Mu
 
@@ -89,6 +93,13 @@ pub type Result = core::exception::Result<Tag>;
 pub type Condition = core::exception::Condition;
 /// the Exception representation
 pub type Exception = core::exception::Exception;
+/// the source location of a read form
+pub type Location = core::reader::Location;
+/// the runtime configuration, its depth assumes evaluation runs
+/// under System::with_stack unless its stack is 0
+pub type Config = core::config::Config;
+/// a dynamic frame's function, named variables and parent
+pub type FrameInfo = core::dynamic::FrameInfo;
 
 /// the Mu struct abstracts the core library struct
 pub struct Mu(core::mu::Mu);
@@ -127,6 +138,21 @@ impl Mu {
         Compiler::compile(&self.0, expr)
     }
 
+    /// check an uncompiled s-expression, returns a list of warnings and
+    /// writes them to a mu stream if it isn't nil
+    pub fn lint(&self, expr: Tag, stream: Tag) -> exception::Result<Tag> {
+        let warnings = Lint::lint(&self.0, expr);
+
+        if !stream.null_() {
+            match Lint::write(&self.0, &warnings, stream) {
+                Ok(_) => (),
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(Lint::as_list(&self.0, &warnings))
+    }
+
     /// read a tagged s-expression from a mu stream
     pub fn read(&self, stream: Tag, eofp: bool, eof_value: Tag) -> exception::Result<Tag> {
         <mu::Mu as stream::Core>::read(&self.0, stream, eofp, eof_value, false)
@@ -171,6 +197,27 @@ impl Mu {
         tag.as_u64()
     }
 
+    /// set the step hook, a function of (event form frame value) or nil,
+    /// returns the previous hook
+    pub fn step(&self, hook: Tag) -> Tag {
+        Step::set(&self.0, hook)
+    }
+
+    /// the innermost dynamic frame, frames are numbered from the outermost
+    pub fn frame_top(&self) -> Option<usize> {
+        self.0.frame_top()
+    }
+
+    /// inspect a dynamic frame
+    pub fn frame(&self, frame: usize) -> Option<FrameInfo> {
+        self.0.frame_info(frame)
+    }
+
+    /// the symbol an exception backtrace function is bound to, if any
+    pub fn frame_symbol(&self, func: Tag) -> Option<Tag> {
+        self.0.frame_symbol(func)
+    }
+
     /// return the standard-input mu stream
     pub fn std_in(&self) -> Tag {
         self.0.stdin
@@ -215,6 +262,23 @@ impl System {
         Mu::config(&conf.to_string())
     }
 
+    /// run a host function on a thread with the configured evaluation
+    /// stack, or on this one if the configured stack size is zero. the
+    /// default call depth needs more than the usual main thread stack,
+    /// so embedders should evaluate under with_stack, or configure
+    /// stack:0, which lowers the default depth to fit the host's stack.
+    pub fn with_stack(config: &Config, host: impl FnOnce() + Send + 'static) {
+        match config.stack {
+            0 => host(),
+            megabytes => std::thread::Builder::new()
+                .stack_size(megabytes << 20)
+                .spawn(host)
+                .unwrap()
+                .join()
+                .unwrap(),
+        }
+    }
+
     pub fn mu(&self) -> &Mu {
         &self.mu
     }
@@ -230,12 +294,26 @@ impl System {
     }
 
     pub fn error(&self, ex: Exception) -> String {
-        format!(
-            "error: condition {:?} on {} raised by {}",
-            ex.condition,
+        // conditions are reported by the keyword mu raises and handles them as
+        let condition = self.write(Exception::map_condkey(ex.condition).unwrap(), true);
+
+        let message = format!(
+            "error: condition {} on {} raised by {}",
+            condition,
             self.write(ex.object, true),
             self.write(ex.source, true),
-        )
+        );
+
+        let message = if ex.reason.null_() {
+            message
+        } else {
+            format!("{}, {}", message, self.write(ex.reason, false))
+        };
+
+        match ex.location {
+            Some(location) => format!("{} at {}", message, location),
+            None => message,
+        }
     }
 
     pub fn read(&self, string: String) -> Result {
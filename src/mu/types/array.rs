@@ -0,0 +1,452 @@
+//  SPDX-FileCopyrightText: Copyright 2022 James M. Putnam (putnamjm.design@gmail.com)
+//  SPDX-License-Identifier: MIT
+
+//! mu array type
+use crate::{
+    core::{
+        exception::{self, Condition, Exception},
+        frame::Frame,
+        funcall::Core as _,
+        mu::Mu,
+        stream,
+        types::{Tag, Type},
+    },
+    types::{
+        cons::{Cons, ConsIter, Core as _},
+        fixnum::Fixnum,
+        struct_::Struct,
+        symbol::{Core as _, Symbol},
+        vecimage::{TypedVec, VecType, VectorIter},
+        vector::{Core as _, Vector},
+    },
+};
+
+// an array is an :array struct over a base vector
+//
+//     #s(:array #(:fixnum dims...) base offset)
+//
+// elements are stored in row-major order in the base vector
+// starting at offset, displaced arrays share the base vector.
+pub struct Array {
+    pub dims: Vec<usize>,
+    pub base: Tag,
+    pub offset: usize,
+}
+
+impl Array {
+    // an :array struct whose slots describe elements of its base vector
+    pub fn is_array(mu: &Mu, tag: Tag) -> bool {
+        if tag.type_of() != Type::Struct || !Struct::stype(mu, tag).eq_(&Symbol::keyword("array")) {
+            return false;
+        }
+
+        let vector = Struct::vector(mu, tag);
+        if Vector::length(mu, vector) != 3 {
+            return false;
+        }
+
+        let (dims, base, offset) = (
+            Vector::r#ref(mu, vector, 0).unwrap(),
+            Vector::r#ref(mu, vector, 1).unwrap(),
+            Vector::r#ref(mu, vector, 2).unwrap(),
+        );
+
+        if dims.type_of() != Type::Vector
+            || Vector::type_of(mu, dims) != Type::Fixnum
+            || base.type_of() != Type::Vector
+            || offset.type_of() != Type::Fixnum
+            || Fixnum::as_i64(offset) < 0
+        {
+            return false;
+        }
+
+        let size = VectorIter::new(mu, dims).try_fold(1usize, |size, dim| {
+            match usize::try_from(Fixnum::as_i64(dim)) {
+                Ok(dim) => size.checked_mul(dim),
+                Err(_) => None,
+            }
+        });
+
+        match size.and_then(|size| size.checked_add(Fixnum::as_i64(offset) as usize)) {
+            Some(end) => end <= Vector::length(mu, base),
+            None => false,
+        }
+    }
+
+    pub fn to_image(mu: &Mu, tag: Tag) -> Self {
+        let vector = Struct::vector(mu, tag);
+
+        Array {
+            dims: VectorIter::new(mu, Vector::r#ref(mu, vector, 0).unwrap())
+                .map(|dim| Fixnum::as_i64(dim) as usize)
+                .collect(),
+            base: Vector::r#ref(mu, vector, 1).unwrap(),
+            offset: Fixnum::as_i64(Vector::r#ref(mu, vector, 2).unwrap()) as usize,
+        }
+    }
+
+    pub fn evict(&self, mu: &Mu) -> Tag {
+        let dims = self
+            .dims
+            .iter()
+            .map(|dim| *dim as i64)
+            .collect::<Vec<i64>>();
+
+        Struct::to_tag(
+            mu,
+            Symbol::keyword("array"),
+            vec![
+                TypedVec::<Vec<i64>> { vec: dims }.vec.to_vector().evict(mu),
+                self.base,
+                Fixnum::as_tag(self.offset as i64),
+            ],
+        )
+    }
+
+    pub fn size(dims: &[usize]) -> usize {
+        dims.iter().product()
+    }
+
+    // map a list of indices to an index in the base vector
+    fn row_major(&self, mu: &Mu, indices: Tag) -> Option<usize> {
+        if Cons::length(mu, indices) != Some(self.dims.len()) {
+            return None;
+        }
+
+        let mut index = 0;
+
+        for (cons, dim) in ConsIter::new(mu, indices).zip(self.dims.iter()) {
+            let nth = Cons::car(mu, cons);
+
+            if nth.type_of() != Type::Fixnum {
+                return None;
+            }
+
+            let nth = Fixnum::as_i64(nth);
+            if nth < 0 || nth as usize >= *dim {
+                return None;
+            }
+
+            index = (index * dim) + nth as usize;
+        }
+
+        Some(self.offset + index)
+    }
+
+    // displace onto the base vector of an array or onto a vector
+    fn displace(mu: &Mu, tag: Tag, dims: Vec<usize>, offset: usize) -> Option<Self> {
+        let (base, base_offset) = if Self::is_array(mu, tag) {
+            let image = Self::to_image(mu, tag);
+
+            (image.base, image.offset)
+        } else {
+            (tag, 0)
+        };
+
+        if base_offset + offset + Self::size(&dims) > Vector::length(mu, base) {
+            return None;
+        }
+
+        Some(Array {
+            dims,
+            base,
+            offset: base_offset + offset,
+        })
+    }
+
+    fn dims(mu: &Mu, list: Tag) -> Option<Vec<usize>> {
+        let mut dims = Vec::new();
+
+        for cons in ConsIter::new(mu, list) {
+            let dim = Cons::car(mu, cons);
+
+            match dim.type_of() {
+                Type::Fixnum if Fixnum::as_i64(dim) >= 0 => dims.push(Fixnum::as_i64(dim) as usize),
+                _ => return None,
+            }
+        }
+
+        Some(dims)
+    }
+
+    // #<rank>A reader, the rank has already been consumed
+    pub fn read(mu: &Mu, rank: usize, stream: Tag) -> exception::Result<Tag> {
+        // the first list at each level sets that dimension
+        fn flatten(
+            mu: &Mu,
+            contents: Tag,
+            level: usize,
+            rank: usize,
+            dims: &mut Vec<usize>,
+            vec: &mut Vec<Tag>,
+        ) -> bool {
+            if level == rank {
+                vec.push(contents);
+                return true;
+            }
+
+            let len = match contents.type_of() {
+                Type::Null | Type::Cons => match Cons::length(mu, contents) {
+                    Some(len) => len,
+                    None => return false,
+                },
+                _ => return false,
+            };
+
+            if dims.len() == level {
+                dims.push(len)
+            } else if dims[level] != len {
+                return false;
+            }
+
+            ConsIter::new(mu, contents)
+                .all(|cons| flatten(mu, Cons::car(mu, cons), level + 1, rank, dims, vec))
+        }
+
+        match <Mu as stream::Core>::read(mu, stream, false, Tag::nil(), false) {
+            Ok(contents) => {
+                let mut dims = Vec::new();
+                let mut vec = Vec::new();
+
+                if !flatten(mu, contents, 0, rank, &mut dims, &mut vec) || dims.len() != rank {
+                    return Err(Exception::new(Condition::Syntax, "read:#A", contents));
+                }
+
+                Ok(Array {
+                    dims,
+                    base: TypedVec::<Vec<Tag>> { vec }.vec.to_vector().evict(mu),
+                    offset: 0,
+                }
+                .evict(mu))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn write(mu: &Mu, array: Tag, escape: bool, stream: Tag) -> exception::Result<()> {
+        fn write_dims(
+            mu: &Mu,
+            image: &Array,
+            dims: &[usize],
+            index: &mut usize,
+            escape: bool,
+            stream: Tag,
+        ) -> exception::Result<()> {
+            if dims.is_empty() {
+                let elt = Vector::r#ref(mu, image.base, image.offset + *index).unwrap();
+
+                *index += 1;
+                return <Mu as stream::Core>::write(mu, elt, escape, stream);
+            }
+
+            match <Mu as stream::Core>::write_string(mu, "(", stream) {
+                Ok(_) => (),
+                Err(e) => return Err(e),
+            }
+
+            for nth in 0..dims[0] {
+                if nth != 0 {
+                    match <Mu as stream::Core>::write_string(mu, " ", stream) {
+                        Ok(_) => (),
+                        Err(e) => return Err(e),
+                    }
+                }
+
+                match write_dims(mu, image, &dims[1..], index, escape, stream) {
+                    Ok(_) => (),
+                    Err(e) => return Err(e),
+                }
+            }
+
+            <Mu as stream::Core>::write_string(mu, ")", stream)
+        }
+
+        let image = Self::to_image(mu, array);
+        let prefix = match image.dims.len() {
+            0 => "#0A ".to_string(),
+            rank => format!("#{rank}A"),
+        };
+
+        match <Mu as stream::Core>::write_string(mu, &prefix, stream) {
+            Ok(_) => (),
+            Err(e) => return Err(e),
+        }
+
+        write_dims(mu, &image, &image.dims, &mut 0, escape, stream)
+    }
+}
+
+// mu functions
+pub trait MuFunction {
+    fn mu_make_array(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+    fn mu_array_dims(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+    fn mu_array_displace(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+    fn mu_array_rank(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+    fn mu_array_ref(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+    fn mu_array_set(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+    fn mu_array_vector(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+}
+
+impl MuFunction for Array {
+    fn mu_make_array(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        let dims = fp.argv[0];
+        let vector = fp.argv[1];
+
+        fp.value = match mu.fp_argv_check("array", &[Type::List, Type::Vector], fp) {
+            Ok(_) => match Self::dims(mu, dims) {
+                Some(dims_vec) => match Self::displace(mu, vector, dims_vec, 0) {
+                    Some(array) => array.evict(mu),
+                    None => return Err(Exception::new(Condition::Range, "array", dims)),
+                },
+                None => return Err(Exception::new(Condition::Type, "array", dims)),
+            },
+            Err(e) => return Err(e),
+        };
+
+        Ok(())
+    }
+
+    fn mu_array_displace(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        let array = fp.argv[0];
+        let dims = fp.argv[1];
+        let offset = fp.argv[2];
+
+        fp.value = match mu.fp_argv_check("ar-disp", &[Type::T, Type::List, Type::Fixnum], fp) {
+            Ok(_) => {
+                if !(Self::is_array(mu, array) || array.type_of() == Type::Vector) {
+                    return Err(Exception::new(Condition::Type, "ar-disp", array));
+                }
+
+                if Fixnum::as_i64(offset) < 0 {
+                    return Err(Exception::new(Condition::Range, "ar-disp", offset));
+                }
+
+                match Self::dims(mu, dims) {
+                    Some(dims_vec) => {
+                        match Self::displace(mu, array, dims_vec, Fixnum::as_i64(offset) as usize) {
+                            Some(array) => array.evict(mu),
+                            None => return Err(Exception::new(Condition::Range, "ar-disp", dims)),
+                        }
+                    }
+                    None => return Err(Exception::new(Condition::Type, "ar-disp", dims)),
+                }
+            }
+            Err(e) => return Err(e),
+        };
+
+        Ok(())
+    }
+
+    fn mu_array_dims(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        let array = fp.argv[0];
+
+        fp.value = if Self::is_array(mu, array) {
+            let dims = Self::to_image(mu, array)
+                .dims
+                .iter()
+                .map(|dim| Fixnum::as_tag(*dim as i64))
+                .collect::<Vec<Tag>>();
+
+            Cons::vlist(mu, &dims)
+        } else {
+            return Err(Exception::new(Condition::Type, "ar-dims", array));
+        };
+
+        Ok(())
+    }
+
+    fn mu_array_rank(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        let array = fp.argv[0];
+
+        fp.value = if Self::is_array(mu, array) {
+            Fixnum::as_tag(Self::to_image(mu, array).dims.len() as i64)
+        } else {
+            return Err(Exception::new(Condition::Type, "ar-rank", array));
+        };
+
+        Ok(())
+    }
+
+    fn mu_array_vector(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        let array = fp.argv[0];
+
+        fp.value = if Self::is_array(mu, array) {
+            Self::to_image(mu, array).base
+        } else {
+            return Err(Exception::new(Condition::Type, "ar-vec", array));
+        };
+
+        Ok(())
+    }
+
+    fn mu_array_ref(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        let array = fp.argv[0];
+        let indices = fp.argv[1];
+
+        fp.value = match mu.fp_argv_check("ar-ref", &[Type::Struct, Type::List], fp) {
+            Ok(_) => {
+                if !Self::is_array(mu, array) {
+                    return Err(Exception::new(Condition::Type, "ar-ref", array));
+                }
+
+                let image = Self::to_image(mu, array);
+
+                match image.row_major(mu, indices) {
+                    Some(index) => Vector::r#ref(mu, image.base, index).unwrap(),
+                    None => return Err(Exception::new(Condition::Range, "ar-ref", indices)),
+                }
+            }
+            Err(e) => return Err(e),
+        };
+
+        Ok(())
+    }
+
+    fn mu_array_set(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        let array = fp.argv[0];
+        let indices = fp.argv[1];
+        let value = fp.argv[2];
+
+        fp.value = match mu.fp_argv_check("ar-set", &[Type::Struct, Type::List, Type::T], fp) {
+            Ok(_) => {
+                if !Self::is_array(mu, array) {
+                    return Err(Exception::new(Condition::Type, "ar-set", array));
+                }
+
+                let image = Self::to_image(mu, array);
+
+                let vtype = Vector::type_of(mu, image.base);
+
+                if !Vector::is_type(vtype, value) {
+                    return Err(Exception::new(Condition::Type, "ar-set", value));
+                }
+
+                if !Vector::in_range(mu, vtype, value) {
+                    return Err(Exception::new(Condition::Range, "ar-set", value));
+                }
+
+                match image.row_major(mu, indices) {
+                    Some(index) => match Vector::set(mu, image.base, index, value) {
+                        Some(value) => value,
+                        None => return Err(Exception::new(Condition::Type, "ar-set", image.base)),
+                    },
+                    None => return Err(Exception::new(Condition::Range, "ar-set", indices)),
+                }
+            }
+            Err(e) => return Err(e),
+        };
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::array::Array;
+
+    #[test]
+    fn size() {
+        assert_eq!(Array::size(&[2, 3, 4]), 24);
+        assert_eq!(Array::size(&[]), 1);
+    }
+}
@@ -13,8 +13,9 @@ use crate::{
         types::{Tag, TagType, Type},
     },
     types::{
+        cons::{Cons, ConsIter, Core as _},
         fixnum::Fixnum,
-        symbol::Symbol,
+        symbol::{Core as _, Symbol},
         vecimage::{TypedVec, VecType},
         vector::Core as _,
         vector::Vector,
@@ -25,17 +26,101 @@ use futures::executor::block_on;
 
 #[derive(Copy, Clone)]
 pub struct Function {
-    pub arity: Tag, // fixnum # of required arguments
-    pub form: Tag,  // list or native keyword
+    pub arity: Tag, // fixnum # of required arguments, or lambda list vector
+    pub form: Tag,  // list, native keyword, or closed function
+    pub env: Tag,   // closed functions or captured frames
+}
+
+// lambda list descriptor, #(:t nreqs nopts rest keys defaults)
+//
+// defaults are compiled forms for the optional and keyword
+// arguments, in lambda list order
+pub struct LambdaList {
+    pub nreqs: usize,
+    pub nopts: usize,
+    pub rest: bool,
+    pub keys: Vec<Tag>,
+    pub defaults: Vec<Tag>,
+}
+
+impl LambdaList {
+    pub fn new(nreqs: usize) -> Self {
+        LambdaList {
+            nreqs,
+            nopts: 0,
+            rest: false,
+            keys: Vec::new(),
+            defaults: Vec::new(),
+        }
+    }
+
+    pub fn is_fixed(&self) -> bool {
+        self.nopts == 0 && !self.rest && self.keys.is_empty()
+    }
+
+    pub fn accepts(&self, nargs: usize) -> bool {
+        nargs >= self.nreqs
+            && (self.rest || !self.keys.is_empty() || nargs <= self.nreqs + self.nopts)
+    }
+
+    pub fn arity(&self, mu: &Mu) -> Tag {
+        if self.is_fixed() {
+            Fixnum::as_tag(self.nreqs as i64)
+        } else {
+            let vec = vec![
+                Fixnum::as_tag(self.nreqs as i64),
+                Fixnum::as_tag(self.nopts as i64),
+                if self.rest {
+                    Symbol::keyword("t")
+                } else {
+                    Tag::nil()
+                },
+                Cons::vlist(mu, &self.keys),
+                Cons::vlist(mu, &self.defaults),
+            ];
+
+            TypedVec::<Vec<Tag>> { vec }.vec.to_vector().evict(mu)
+        }
+    }
+
+    pub fn from_arity(mu: &Mu, arity: Tag) -> Self {
+        match arity.type_of() {
+            Type::Fixnum => Self::new(Fixnum::as_i64(arity) as usize),
+            Type::Vector => {
+                let list = |nth| {
+                    ConsIter::new(mu, Vector::r#ref(mu, arity, nth).unwrap())
+                        .map(|cons| Cons::car(mu, cons))
+                        .collect::<Vec<Tag>>()
+                };
+
+                LambdaList {
+                    nreqs: Fixnum::as_i64(Vector::r#ref(mu, arity, 0).unwrap()) as usize,
+                    nopts: Fixnum::as_i64(Vector::r#ref(mu, arity, 1).unwrap()) as usize,
+                    rest: !Vector::r#ref(mu, arity, 2).unwrap().null_(),
+                    keys: list(3),
+                    defaults: list(4),
+                }
+            }
+            _ => panic!(),
+        }
+    }
 }
 
 impl Function {
     pub fn new(arity: Tag, form: Tag) -> Self {
-        Function { arity, form }
+        Function {
+            arity,
+            form,
+            env: Tag::nil(),
+        }
     }
 
     pub fn evict(&self, mu: &Mu) -> Tag {
-        let image: &[[u8; 8]] = &[self.arity.as_slice(), self.form.as_slice()];
+        let image: &[[u8; 8]] = &[
+            self.arity.as_slice(),
+            self.form.as_slice(),
+            self.env.as_slice(),
+        ];
 
         let mut heap_ref = block_on(mu.heap.write());
         let ind = IndirectTag::new()
@@ -61,6 +146,11 @@ impl Function {
                                 .image_slice(main.image_id() as usize + 8, 8)
                                 .unwrap(),
                         ),
+                        env: Tag::from_slice(
+                            heap_ref
+                                .image_slice(main.image_id() as usize + 16, 8)
+                                .unwrap(),
+                        ),
                     }
                 }
                 _ => panic!(),
@@ -70,7 +160,11 @@ impl Function {
     }
 
     pub fn update(mu: &Mu, image: &Function, func: Tag) {
-        let slices: &[[u8; 8]] = &[image.arity.as_slice(), image.form.as_slice()];
+        let slices: &[[u8; 8]] = &[
+            image.arity.as_slice(),
+            image.form.as_slice(),
+            image.env.as_slice(),
+        ];
 
         let offset = match func {
             Tag::Indirect(heap) => heap.image_id(),
@@ -89,6 +183,14 @@ impl Function {
     pub fn form(mu: &Mu, func: Tag) -> Tag {
         Self::to_image(mu, func).form
     }
+
+    pub fn lambda_list(mu: &Mu, func: Tag) -> LambdaList {
+        LambdaList::from_arity(mu, Self::arity(mu, func))
+    }
+
+    pub fn env(mu: &Mu, func: Tag) -> Tag {
+        Self::to_image(mu, func).env
+    }
 }
 
 pub trait Core {
@@ -103,7 +205,9 @@ impl Core for Function {
         let mark = Heap::mark(mu, function).unwrap();
 
         if !mark {
-            Mu::gc_mark(mu, Self::form(mu, function))
+            Mu::gc_mark(mu, Self::arity(mu, function));
+            Mu::gc_mark(mu, Self::form(mu, function));
+            Mu::gc_mark(mu, Self::env(mu, function))
         }
     }
 
@@ -115,7 +219,7 @@ impl Core for Function {
 
     fn heap_size(mu: &Mu, fn_: Tag) -> usize {
         match Self::form(mu, fn_).type_of() {
-            Type::Null | Type::Cons => std::mem::size_of::<Function>(),
+            Type::Null | Type::Cons | Type::Function => std::mem::size_of::<Function>(),
             Type::Keyword => std::mem::size_of::<Function>(),
             _ => panic!(),
         }
@@ -124,13 +228,29 @@ impl Core for Function {
     fn write(mu: &Mu, func: Tag, _: bool, stream: Tag) -> exception::Result<()> {
         match func.type_of() {
             Type::Function => {
-                let nreq = Fixnum::as_i64(Function::arity(mu, func));
+                let lambda_list = Function::lambda_list(mu, func);
                 let form = Function::form(mu, func);
 
+                let args = if lambda_list.is_fixed() {
+                    format!("req:{}", lambda_list.nreqs)
+                } else {
+                    format!(
+                        "req:{}, opt:{}, rest:{}, key:{}",
+                        lambda_list.nreqs,
+                        lambda_list.nopts,
+                        lambda_list.rest as usize,
+                        lambda_list.keys.len()
+                    )
+                };
+
                 let desc = match form.type_of() {
                     Type::Cons | Type::Null => {
                         (":lambda".to_string(), format!("{:x}", form.as_u64()))
                     }
+                    Type::Function => (
+                        ":closure".to_string(),
+                        format!("{:x}", Function::form(mu, form).as_u64()),
+                    ),
                     Type::Keyword => (
                         ":native".to_string(),
                         Vector::as_string(mu, Symbol::name(mu, form)).to_string(),
@@ -140,7 +260,7 @@ impl Core for Function {
 
                 <Mu as stream::Core>::write_string(
                     mu,
-                    format!("#<:function {} [req:{nreq}, form:{}]>", desc.0, desc.1).as_str(),
+                    format!("#<:function {} [{args}, form:{}]>", desc.0, desc.1).as_str(),
                     stream,
                 )
             }
@@ -0,0 +1,389 @@
+//  SPDX-FileCopyrightText: Copyright 2022 James M. Putnam (putnamjm.design@gmail.com)
+//  SPDX-License-Identifier: MIT
+
+//! mu persistent maps
+//!
+//! a persistent map is a hash array mapped trie kept in the heap.
+//! interior nodes are general vectors, #(bitmap entry...), where
+//! an entry is either a (key . value) leaf or another node. keys
+//! whose hashes collide completely share a collision node,
+//! #(:nil leaf...). updates copy the path to the changed leaf and
+//! share everything else with the original trie.
+use {
+    crate::{
+        core::{
+            equal::{Core as _, FnvHasher},
+            exception::{self, Condition, Exception},
+            frame::Frame,
+            funcall::Core as _,
+            mu::Mu,
+            types::{Tag, Type},
+        },
+        types::{
+            cons::{Cons, ConsIter, Core as _},
+            fixnum::Fixnum,
+            map::Map,
+            symbol::{Core as _, Symbol},
+            vecimage::{TypedVec, VecType, VectorIter},
+            vector::Core as _,
+        },
+    },
+    std::hash::{Hash, Hasher},
+};
+
+pub struct Hamt {
+    equal: bool, // structural key equality
+}
+
+impl Hamt {
+    const BITS: u32 = 5;
+    const MASK: u64 = (1 << Self::BITS) - 1;
+
+    pub fn new(mode: Tag) -> Self {
+        Hamt {
+            equal: mode.eq_(&Symbol::keyword("equal")),
+        }
+    }
+
+    fn hash(&self, mu: &Mu, key: Tag) -> u64 {
+        if self.equal {
+            Tag::hash(mu, key)
+        } else {
+            let mut hasher = FnvHasher::default();
+
+            key.as_u64().hash(&mut hasher);
+            hasher.finish()
+        }
+    }
+
+    fn key_eq(&self, mu: &Mu, key: Tag, key1: Tag) -> bool {
+        if self.equal {
+            Tag::equal(mu, key, key1)
+        } else {
+            key.eq_(&key1)
+        }
+    }
+
+    fn node(mu: &Mu, vec: Vec<Tag>) -> Tag {
+        TypedVec::<Vec<Tag>> { vec }.vec.to_vector().evict(mu)
+    }
+
+    fn entries(mu: &Mu, node: Tag) -> Vec<Tag> {
+        VectorIter::new(mu, node).collect::<Vec<Tag>>()
+    }
+
+    fn slot(hash: u64, shift: u32) -> u64 {
+        1 << ((hash >> shift) & Self::MASK)
+    }
+
+    fn position(bitmap: u64, bit: u64) -> usize {
+        1 + (bitmap & (bit - 1)).count_ones() as usize
+    }
+
+    pub fn get(&self, mu: &Mu, node: Tag, key: Tag) -> Option<Tag> {
+        let hash = self.hash(mu, key);
+        let mut node = node;
+        let mut shift = 0;
+
+        loop {
+            if node.null_() {
+                return None;
+            }
+
+            let entries = Self::entries(mu, node);
+
+            if entries[0].null_() {
+                return entries[1..]
+                    .iter()
+                    .find(|leaf| self.key_eq(mu, Cons::car(mu, **leaf), key))
+                    .map(|leaf| Cons::cdr(mu, *leaf));
+            }
+
+            let bitmap = Fixnum::as_i64(entries[0]) as u64;
+            let bit = Self::slot(hash, shift);
+
+            if bitmap & bit == 0 {
+                return None;
+            }
+
+            let entry = entries[Self::position(bitmap, bit)];
+
+            match entry.type_of() {
+                Type::Cons => {
+                    return if self.key_eq(mu, Cons::car(mu, entry), key) {
+                        Some(Cons::cdr(mu, entry))
+                    } else {
+                        None
+                    }
+                }
+                _ => {
+                    node = entry;
+                    shift += Self::BITS
+                }
+            }
+        }
+    }
+
+    // a node holding two leaves whose hashes agree below shift
+    fn merge(&self, mu: &Mu, leaf: Tag, leaf1: Tag, shift: u32) -> Tag {
+        if shift >= u64::BITS {
+            return Self::node(mu, vec![Tag::nil(), leaf, leaf1]);
+        }
+
+        let bit = Self::slot(self.hash(mu, Cons::car(mu, leaf)), shift);
+        let bit1 = Self::slot(self.hash(mu, Cons::car(mu, leaf1)), shift);
+
+        let entries = if bit == bit1 {
+            vec![
+                Fixnum::as_tag(bit as i64),
+                self.merge(mu, leaf, leaf1, shift + Self::BITS),
+            ]
+        } else if bit < bit1 {
+            vec![Fixnum::as_tag((bit | bit1) as i64), leaf, leaf1]
+        } else {
+            vec![Fixnum::as_tag((bit | bit1) as i64), leaf1, leaf]
+        };
+
+        Self::node(mu, entries)
+    }
+
+    // returns the new node and whether the key was added
+    pub fn assoc(&self, mu: &Mu, node: Tag, key: Tag, value: Tag) -> (Tag, bool) {
+        let leaf = Cons::new(key, value).evict(mu);
+
+        if node.null_() {
+            let bit = Self::slot(self.hash(mu, key), 0);
+
+            return (Self::node(mu, vec![Fixnum::as_tag(bit as i64), leaf]), true);
+        }
+
+        self.assoc_(mu, node, self.hash(mu, key), leaf, 0)
+    }
+
+    fn assoc_(&self, mu: &Mu, node: Tag, hash: u64, leaf: Tag, shift: u32) -> (Tag, bool) {
+        let key = Cons::car(mu, leaf);
+        let mut entries = Self::entries(mu, node);
+
+        if entries[0].null_() {
+            return match entries[1..]
+                .iter()
+                .position(|entry| self.key_eq(mu, Cons::car(mu, *entry), key))
+            {
+                Some(nth) => {
+                    entries[nth + 1] = leaf;
+                    (Self::node(mu, entries), false)
+                }
+                None => {
+                    entries.push(leaf);
+                    (Self::node(mu, entries), true)
+                }
+            };
+        }
+
+        let bitmap = Fixnum::as_i64(entries[0]) as u64;
+        let bit = Self::slot(hash, shift);
+        let nth = Self::position(bitmap, bit);
+
+        if bitmap & bit == 0 {
+            entries[0] = Fixnum::as_tag((bitmap | bit) as i64);
+            entries.insert(nth, leaf);
+
+            return (Self::node(mu, entries), true);
+        }
+
+        let entry = entries[nth];
+        let added = match entry.type_of() {
+            Type::Cons => {
+                if self.key_eq(mu, Cons::car(mu, entry), key) {
+                    entries[nth] = leaf;
+                    false
+                } else {
+                    entries[nth] = self.merge(mu, entry, leaf, shift + Self::BITS);
+                    true
+                }
+            }
+            _ => {
+                let (child, added) = self.assoc_(mu, entry, hash, leaf, shift + Self::BITS);
+
+                entries[nth] = child;
+                added
+            }
+        };
+
+        (Self::node(mu, entries), added)
+    }
+
+    // returns None if the key isn't present, otherwise the
+    // replacement for node: :nil if empty, a leaf, or a node
+    pub fn dissoc(&self, mu: &Mu, node: Tag, key: Tag) -> Option<Tag> {
+        if node.null_() {
+            return None;
+        }
+
+        self.dissoc_(mu, node, self.hash(mu, key), key, 0)
+    }
+
+    fn dissoc_(&self, mu: &Mu, node: Tag, hash: u64, key: Tag, shift: u32) -> Option<Tag> {
+        let mut entries = Self::entries(mu, node);
+
+        if entries[0].null_() {
+            let nth = entries[1..]
+                .iter()
+                .position(|entry| self.key_eq(mu, Cons::car(mu, *entry), key))?;
+
+            entries.remove(nth + 1);
+
+            return Some(match entries.len() {
+                2 => entries[1],
+                _ => Self::node(mu, entries),
+            });
+        }
+
+        let bitmap = Fixnum::as_i64(entries[0]) as u64;
+        let bit = Self::slot(hash, shift);
+        let nth = Self::position(bitmap, bit);
+
+        if bitmap & bit == 0 {
+            return None;
+        }
+
+        let entry = entries[nth];
+        let replacement = match entry.type_of() {
+            Type::Cons => {
+                if self.key_eq(mu, Cons::car(mu, entry), key) {
+                    Tag::nil()
+                } else {
+                    return None;
+                }
+            }
+            _ => self.dissoc_(mu, entry, hash, key, shift + Self::BITS)?,
+        };
+
+        if replacement.null_() {
+            entries[0] = Fixnum::as_tag((bitmap & !bit) as i64);
+            entries.remove(nth);
+        } else {
+            entries[nth] = replacement;
+        }
+
+        Some(match entries.len() {
+            1 => Tag::nil(),
+            2 if shift != 0 && entries[1].type_of() == Type::Cons => entries[1],
+            _ => Self::node(mu, entries),
+        })
+    }
+
+    // the (key . value) leaves of a trie
+    pub fn leaves(mu: &Mu, node: Tag) -> Vec<Tag> {
+        let mut leaves = Vec::new();
+
+        fn walk(mu: &Mu, node: Tag, leaves: &mut Vec<Tag>) {
+            for entry in Hamt::entries(mu, node).into_iter().skip(1) {
+                match entry.type_of() {
+                    Type::Cons => leaves.push(entry),
+                    _ => walk(mu, entry, leaves),
+                }
+            }
+        }
+
+        if !node.null_() {
+            walk(mu, node, &mut leaves)
+        }
+
+        leaves
+    }
+}
+
+pub trait MuFunction {
+    fn mu_make_hamt(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+    fn mu_assoc(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+    fn mu_dissoc(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+}
+
+impl MuFunction for Hamt {
+    fn mu_make_hamt(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        let mode = fp.argv[0];
+        let list = fp.argv[1];
+
+        fp.value = match mu.fp_argv_check("hamt", &[Type::Keyword, Type::List], fp) {
+            Ok(_) => {
+                if !mode.eq_(&Symbol::keyword("eq")) && !mode.eq_(&Symbol::keyword("equal")) {
+                    return Err(Exception::new(Condition::Type, "hamt", mode));
+                }
+
+                let hamt = Self::new(mode);
+                let mut root = Tag::nil();
+                let mut size = 0;
+
+                for cons in ConsIter::new(mu, list) {
+                    let pair = Cons::car(mu, cons);
+
+                    if pair.type_of() != Type::Cons {
+                        return Err(Exception::new(Condition::Type, "hamt", pair));
+                    }
+
+                    let (node, added) =
+                        hamt.assoc(mu, root, Cons::car(mu, pair), Cons::cdr(mu, pair));
+
+                    root = node;
+                    if added {
+                        size += 1
+                    }
+                }
+
+                Map::persistent(mu, mode, size, root)
+            }
+            Err(e) => return Err(e),
+        };
+
+        Ok(())
+    }
+
+    fn mu_assoc(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        let map = fp.argv[0];
+        let key = fp.argv[1];
+        let value = fp.argv[2];
+
+        fp.value = match mu.fp_argv_check("assoc", &[Type::Map, Type::T, Type::T], fp) {
+            Ok(_) => match Map::hamt(mu, map) {
+                Some((mode, size, root)) => {
+                    let (root, added) = Self::new(mode).assoc(mu, root, key, value);
+
+                    Map::persistent(mu, mode, if added { size + 1 } else { size }, root)
+                }
+                None => return Err(Exception::new(Condition::Type, "assoc", map)),
+            },
+            Err(e) => return Err(e),
+        };
+
+        Ok(())
+    }
+
+    fn mu_dissoc(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        let map = fp.argv[0];
+        let key = fp.argv[1];
+
+        fp.value = match mu.fp_argv_check("dissoc", &[Type::Map, Type::T], fp) {
+            Ok(_) => match Map::hamt(mu, map) {
+                Some((mode, size, root)) => match Self::new(mode).dissoc(mu, root, key) {
+                    Some(root) => Map::persistent(mu, mode, size - 1, root),
+                    None => map,
+                },
+                None => return Err(Exception::new(Condition::Type, "dissoc", map)),
+            },
+            Err(e) => return Err(e),
+        };
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::hamt::Hamt;
+
+    #[test]
+    fn position() {
+        assert_eq!(Hamt::position(0b1011, 0b1000), 3)
+    }
+}
@@ -5,6 +5,7 @@
 use {
     crate::{
         core::{
+            equal::Core as _,
             exception::{self, Condition, Exception},
             frame::Frame,
             funcall::Core as _,
@@ -15,8 +16,9 @@ use {
             types::{Tag, TagType, Type},
         },
         types::{
-            cons::{Cons, ConsIter},
+            cons::{Cons, ConsIter, Core as _},
             fixnum::Fixnum,
+            hamt::Hamt,
             symbol::{Core as _, Symbol},
             vecimage::{TypedVec, VecType},
             vector::Core as _,
@@ -33,19 +35,134 @@ pub struct Map {
     list: Tag,     // list of pairs
 }
 
+// the rust side of a map
+//
+// entries are kept in insertion order, deleted entries are
+// left as None until the cache is compacted. the heap list is
+// rebuilt from the entries when it is asked for after an update.
+pub struct MapCache {
+    pub equal: bool,
+    pub entries: Vec<Option<(Tag, Tag)>>,
+    pub index: HashMap<u64, Vec<usize>>,
+    pub size: usize,
+    pub dirty: bool,
+}
+
+impl MapCache {
+    fn new(equal: bool) -> Self {
+        MapCache {
+            equal,
+            entries: Vec::new(),
+            index: HashMap::new(),
+            size: 0,
+            dirty: false,
+        }
+    }
+
+    fn hash(&self, mu: &Mu, key: Tag) -> u64 {
+        if self.equal {
+            Tag::hash(mu, key)
+        } else {
+            key.as_u64()
+        }
+    }
+
+    fn find(&self, mu: &Mu, key: Tag) -> Option<usize> {
+        match self.index.get(&self.hash(mu, key)) {
+            Some(bucket) => bucket.iter().copied().find(|nth| match self.entries[*nth] {
+                Some((entry, _)) => {
+                    if self.equal {
+                        Tag::equal(mu, entry, key)
+                    } else {
+                        entry.eq_(&key)
+                    }
+                }
+                None => false,
+            }),
+            None => None,
+        }
+    }
+
+    pub fn get(&self, mu: &Mu, key: Tag) -> Option<Tag> {
+        self.find(mu, key).map(|nth| self.entries[nth].unwrap().1)
+    }
+
+    pub fn insert(&mut self, mu: &Mu, key: Tag, value: Tag) {
+        match self.find(mu, key) {
+            Some(nth) => self.entries[nth] = Some((key, value)),
+            None => {
+                let hash = self.hash(mu, key);
+
+                self.entries.push(Some((key, value)));
+                self.index
+                    .entry(hash)
+                    .or_default()
+                    .push(self.entries.len() - 1);
+                self.size += 1;
+            }
+        }
+
+        self.dirty = true;
+    }
+
+    pub fn remove(&mut self, mu: &Mu, key: Tag) -> bool {
+        match self.find(mu, key) {
+            Some(nth) => {
+                let hash = self.hash(mu, key);
+
+                self.entries[nth] = None;
+                self.index
+                    .get_mut(&hash)
+                    .unwrap()
+                    .retain(|index| *index != nth);
+                self.size -= 1;
+                self.dirty = true;
+
+                if self.entries.len() > 2 * self.size + 8 {
+                    self.compact(mu)
+                }
+
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.index.clear();
+        self.size = 0;
+        self.dirty = true;
+    }
+
+    pub fn pairs(&self) -> Vec<(Tag, Tag)> {
+        self.entries.iter().flatten().copied().collect()
+    }
+
+    fn compact(&mut self, mu: &Mu) {
+        let pairs = self.pairs();
+
+        self.clear();
+        for (key, value) in pairs {
+            self.insert(mu, key, value)
+        }
+    }
+}
+
 impl Map {
-    fn new(mu: &Mu, list: Tag) -> Self {
+    fn new(mu: &Mu, list: Tag, equal: bool) -> Self {
         let mut index_ref = block_on(mu.map_index.write());
         let cache_id = index_ref.len();
-        let mut map = HashMap::<u64, Tag>::new();
+        let mut cache = MapCache::new(equal);
 
         for cons in ConsIter::new(mu, list) {
             let pair = Cons::car(mu, cons);
 
-            map.insert(Tag::as_u64(&Cons::car(mu, pair)), Cons::cdr(mu, pair));
+            cache.insert(mu, Cons::car(mu, pair), Cons::cdr(mu, pair));
         }
 
-        index_ref.insert(cache_id, map);
+        cache.dirty = false;
+        index_ref.insert(cache_id, cache);
 
         Map {
             cache_id: Fixnum::as_tag(cache_id as i64),
@@ -76,31 +193,126 @@ impl Map {
         }
     }
 
-    fn cache_id(mu: &Mu, map: Tag) -> Tag {
-        Self::to_image(mu, map).cache_id
+    fn update(mu: &Mu, image: &Map, map: Tag) {
+        let slices: &[[u8; 8]] = &[image.cache_id.as_slice(), image.list.as_slice()];
+
+        let offset = match map {
+            Tag::Indirect(heap) => heap.image_id(),
+            _ => panic!(),
+        } as usize;
+
+        let mut heap_ref = block_on(mu.heap.write());
+
+        heap_ref.write_image(slices, offset);
+    }
+
+    fn cache_id(mu: &Mu, map: Tag) -> usize {
+        Fixnum::as_i64(Self::to_image(mu, map).cache_id) as usize
     }
 
-    fn list(mu: &Mu, map: Tag) -> Tag {
-        Self::to_image(mu, map).list
+    // persistent maps keep their key mode in the cache id slot
+    // and (size . trie) in the list slot
+    pub fn persistent(mu: &Mu, mode: Tag, size: usize, root: Tag) -> Tag {
+        Map {
+            cache_id: mode,
+            list: Cons::new(Fixnum::as_tag(size as i64), root).evict(mu),
+        }
+        .evict(mu)
     }
 
-    fn view(mu: &Mu, map: Tag) -> Tag {
+    pub fn hamt(mu: &Mu, map: Tag) -> Option<(Tag, usize, Tag)> {
         let image = Self::to_image(mu, map);
-        let vec = vec![image.cache_id, image.list];
 
-        TypedVec::<Vec<Tag>> { vec }.vec.to_vector().evict(mu)
+        match image.cache_id.type_of() {
+            Type::Keyword => Some((
+                image.cache_id,
+                Fixnum::as_i64(Cons::car(mu, image.list)) as usize,
+                Cons::cdr(mu, image.list),
+            )),
+            _ => None,
+        }
     }
 
-    fn heap_size(_: &Mu, _: Tag) -> usize {
-        std::mem::size_of::<Map>()
+    pub fn size(mu: &Mu, map: Tag) -> usize {
+        match Self::hamt(mu, map) {
+            Some((_, size, _)) => size,
+            None => {
+                let index_ref = block_on(mu.map_index.read());
+
+                index_ref[&Self::cache_id(mu, map)].size
+            }
+        }
     }
 
-    fn map_ref(mu: &Mu, cache_id: usize, key: Tag) -> Option<Tag> {
-        let index_ref = block_on(mu.map_index.read());
+    // keys compared with equal rather than eq
+    pub fn is_equal(mu: &Mu, map: Tag) -> bool {
+        match Self::hamt(mu, map) {
+            Some((mode, _, _)) => mode.eq_(&Symbol::keyword("equal")),
+            None => {
+                let index_ref = block_on(mu.map_index.read());
 
-        match index_ref.get(&cache_id) {
-            Some(hash) => hash.get(&key.as_u64()).copied(),
-            None => None,
+                index_ref[&Self::cache_id(mu, map)].equal
+            }
+        }
+    }
+
+    pub fn pairs(mu: &Mu, map: Tag) -> Vec<(Tag, Tag)> {
+        match Self::hamt(mu, map) {
+            Some((_, _, root)) => Hamt::leaves(mu, root)
+                .into_iter()
+                .map(|leaf| (Cons::car(mu, leaf), Cons::cdr(mu, leaf)))
+                .collect(),
+            None => {
+                let index_ref = block_on(mu.map_index.read());
+
+                index_ref[&Self::cache_id(mu, map)].pairs()
+            }
+        }
+    }
+
+    // rebuild the heap list if the cache has been updated
+    fn list(mu: &Mu, map: Tag) -> Tag {
+        if let Some((_, _, root)) = Self::hamt(mu, map) {
+            return Cons::vlist(mu, &Hamt::leaves(mu, root));
+        }
+
+        let mut image = Self::to_image(mu, map);
+        let pairs = {
+            let mut index_ref = block_on(mu.map_index.write());
+            let cache = index_ref
+                .get_mut(&(Fixnum::as_i64(image.cache_id) as usize))
+                .unwrap();
+
+            if !cache.dirty {
+                return image.list;
+            }
+
+            cache.dirty = false;
+            cache.pairs()
+        };
+
+        let pairs = pairs
+            .iter()
+            .map(|(key, value)| Cons::new(*key, *value).evict(mu))
+            .collect::<Vec<Tag>>();
+
+        image.list = Cons::vlist(mu, &pairs);
+        Self::update(mu, &image, map);
+
+        image.list
+    }
+
+    pub fn map_ref(mu: &Mu, map: Tag, key: Tag) -> Option<Tag> {
+        match Self::hamt(mu, map) {
+            Some((mode, _, root)) => Hamt::new(mode).get(mu, root, key),
+            None => {
+                let index_ref = block_on(mu.map_index.read());
+
+                match index_ref.get(&Self::cache_id(mu, map)) {
+                    Some(cache) => cache.get(mu, key),
+                    None => None,
+                }
+            }
         }
     }
 
@@ -130,30 +342,32 @@ impl Core for Map {
         let mark = Heap::mark(mu, map).unwrap();
 
         if !mark {
-            mu.gc_mark(Self::list(mu, map))
+            mu.gc_mark(Self::to_image(mu, map).list)
         }
     }
 
     fn view(mu: &Mu, map: Tag) -> Tag {
-        let vec = vec![Self::cache_id(mu, map), Self::list(mu, map)];
+        let image = Self::to_image(mu, map);
+        let vec = vec![image.cache_id, Self::list(mu, map)];
 
         TypedVec::<Vec<Tag>> { vec }.vec.to_vector().evict(mu)
     }
 
-    fn heap_size(mu: &Mu, symbol: Tag) -> usize {
-        std::mem::size_of::<Map>() + Heap::heap_size(mu, Self::list(mu, symbol))
+    fn heap_size(mu: &Mu, map: Tag) -> usize {
+        std::mem::size_of::<Map>() + Heap::heap_size(mu, Self::list(mu, map))
     }
 
     fn write(mu: &Mu, map: Tag, _: bool, stream: Tag) -> exception::Result<()> {
         match map.type_of() {
             Type::Map => {
-                let cache_id = Fixnum::as_i64(Self::cache_id(mu, map));
-                let index_ref = block_on(mu.map_index.write());
-                let size = index_ref.len();
+                let tag = match Self::hamt(mu, map) {
+                    Some(_) => "hamt".to_string(),
+                    None => Self::cache_id(mu, map).to_string(),
+                };
 
                 <Mu as stream::Core>::write_string(
                     mu,
-                    format!("#<:map [size:{size}, tag:{}]>", cache_id).as_str(),
+                    format!("#<:map [size:{}, tag:{tag}]>", Self::size(mu, map)).as_str(),
                     stream,
                 )
             }
@@ -168,7 +382,7 @@ impl Core for Map {
         let ind = IndirectTag::new()
             .with_image_id(heap_ref.alloc(image, Type::Map as u8) as u64)
             .with_heap_id(1)
-            .with_tag(TagType::Function);
+            .with_tag(TagType::Map);
 
         Tag::Indirect(ind)
     }
@@ -176,9 +390,14 @@ impl Core for Map {
 
 pub trait MuFunction {
     fn mu_make_map(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+    fn mu_make_map_type(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+    fn mu_map_clear(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+    fn mu_map_del(_: &Mu, _: &mut Frame) -> exception::Result<()>;
     fn mu_map_has(_: &Mu, _: &mut Frame) -> exception::Result<()>;
     fn mu_map_items(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+    fn mu_map_map(_: &Mu, _: &mut Frame) -> exception::Result<()>;
     fn mu_map_ref(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+    fn mu_map_set(_: &Mu, _: &mut Frame) -> exception::Result<()>;
     fn mu_map_size(_: &Mu, _: &mut Frame) -> exception::Result<()>;
 }
 
@@ -194,7 +413,39 @@ impl MuFunction for Map {
                     }
                 }
 
-                Map::new(mu, list).evict(mu)
+                Map::new(mu, list, false).evict(mu)
+            }
+            Err(e) => return Err(e),
+        };
+
+        Ok(())
+    }
+
+    fn mu_make_map_type(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        let map_type = fp.argv[0];
+        let list = fp.argv[1];
+
+        fp.value = match mu.fp_argv_check("mp-make", &[Type::Keyword, Type::List], fp) {
+            Ok(_) => {
+                let equal = if map_type.eq_(&Symbol::keyword("equal")) {
+                    true
+                } else if map_type.eq_(&Symbol::keyword("eq")) {
+                    false
+                } else {
+                    return Err(Exception::new(Condition::Type, "mp-make", map_type));
+                };
+
+                for cons in ConsIter::new(mu, list) {
+                    if Cons::car(mu, cons).type_of() != Type::Cons {
+                        return Err(Exception::new(
+                            Condition::Type,
+                            "mp-make",
+                            Cons::car(mu, cons),
+                        ));
+                    }
+                }
+
+                Map::new(mu, list, equal).evict(mu)
             }
             Err(e) => return Err(e),
         };
@@ -207,13 +458,48 @@ impl MuFunction for Map {
         let key = fp.argv[1];
 
         fp.value = match mu.fp_argv_check("mp-ref", &[Type::Map, Type::T], fp) {
+            Ok(_) => match Map::map_ref(mu, map, key) {
+                Some(value) => value,
+                None => return Err(Exception::new(Condition::Range, "mp-ref", key)),
+            },
+            Err(e) => return Err(e),
+        };
+
+        Ok(())
+    }
+
+    fn mu_map_has(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        let map = fp.argv[0];
+        let key = fp.argv[1];
+
+        fp.value = match mu.fp_argv_check("mp-has", &[Type::Map, Type::T], fp) {
+            Ok(_) => match Map::map_ref(mu, map, key) {
+                Some(_) => Symbol::keyword("t"),
+                None => Tag::nil(),
+            },
+            Err(e) => return Err(e),
+        };
+
+        Ok(())
+    }
+
+    fn mu_map_set(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        let map = fp.argv[0];
+        let key = fp.argv[1];
+        let value = fp.argv[2];
+
+        fp.value = match mu.fp_argv_check("mp-set", &[Type::Map, Type::T, Type::T], fp) {
             Ok(_) => {
+                if Map::hamt(mu, map).is_some() {
+                    return Err(Exception::new(Condition::Type, "mp-set", map));
+                }
+
                 let cache_id = Map::cache_id(mu, map);
+                let mut index_ref = block_on(mu.map_index.write());
 
-                match Map::map_ref(mu, Fixnum::as_i64(cache_id) as usize, key) {
-                    Some(value) => value,
-                    None => return Err(Exception::new(Condition::Range, "mp-ref", key)),
-                }
+                index_ref.get_mut(&cache_id).unwrap().insert(mu, key, value);
+
+                value
             }
             Err(e) => return Err(e),
         };
@@ -221,18 +507,46 @@ impl MuFunction for Map {
         Ok(())
     }
 
-    fn mu_map_has(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+    fn mu_map_del(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
         let map = fp.argv[0];
         let key = fp.argv[1];
 
-        fp.value = match mu.fp_argv_check("mp-has", &[Type::Map, Type::T], fp) {
+        fp.value = match mu.fp_argv_check("mp-del", &[Type::Map, Type::T], fp) {
             Ok(_) => {
+                if Map::hamt(mu, map).is_some() {
+                    return Err(Exception::new(Condition::Type, "mp-del", map));
+                }
+
                 let cache_id = Map::cache_id(mu, map);
+                let mut index_ref = block_on(mu.map_index.write());
+
+                if index_ref.get_mut(&cache_id).unwrap().remove(mu, key) {
+                    Symbol::keyword("t")
+                } else {
+                    Tag::nil()
+                }
+            }
+            Err(e) => return Err(e),
+        };
+
+        Ok(())
+    }
+
+    fn mu_map_clear(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        let map = fp.argv[0];
 
-                match Map::map_ref(mu, Fixnum::as_i64(cache_id) as usize, key) {
-                    Some(_) => Symbol::keyword("t"),
-                    None => Tag::nil(),
+        fp.value = match mu.fp_argv_check("mp-clr", &[Type::Map], fp) {
+            Ok(_) => {
+                if Map::hamt(mu, map).is_some() {
+                    return Err(Exception::new(Condition::Type, "mp-clr", map));
                 }
+
+                let cache_id = Map::cache_id(mu, map);
+                let mut index_ref = block_on(mu.map_index.write());
+
+                index_ref.get_mut(&cache_id).unwrap().clear();
+
+                map
             }
             Err(e) => return Err(e),
         };
@@ -251,24 +565,40 @@ impl MuFunction for Map {
         Ok(())
     }
 
-    fn mu_map_size(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
-        let map = fp.argv[0];
+    fn mu_map_map(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        let func = fp.argv[0];
+        let map = fp.argv[1];
 
-        fp.value = match mu.fp_argv_check("mp-size", &[Type::Map], fp) {
+        fp.value = match mu.fp_argv_check("mp-map", &[Type::Function, Type::Map], fp) {
             Ok(_) => {
-                let index_ref = block_on(mu.map_index.read());
-                let cache_id = Map::cache_id(mu, map);
+                let pairs = Map::pairs(mu, map);
+                let mut values = Vec::new();
 
-                match index_ref.get(&(Fixnum::as_i64(cache_id) as usize)) {
-                    Some(hash) => Fixnum::as_tag(hash.len() as i64),
-                    None => panic!(),
+                for (key, value) in pairs {
+                    match mu.apply_(func, vec![key, value]) {
+                        Ok(value) => values.push(value),
+                        Err(e) => return Err(e),
+                    }
                 }
+
+                Cons::vlist(mu, &values)
             }
             Err(e) => return Err(e),
         };
 
         Ok(())
     }
+
+    fn mu_map_size(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        let map = fp.argv[0];
+
+        fp.value = match mu.fp_argv_check("mp-size", &[Type::Map], fp) {
+            Ok(_) => Fixnum::as_tag(Map::size(mu, map) as i64),
+            Err(e) => return Err(e),
+        };
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -2,11 +2,13 @@
 //  SPDX-License-Identifier: MIT
 
 //! mu types module
+pub mod array;
 pub mod char;
 pub mod cons;
 pub mod fixnum;
 pub mod float;
 pub mod function;
+pub mod hamt;
 pub mod map;
 pub mod stream;
 pub mod streambuilder;
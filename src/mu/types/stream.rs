@@ -13,6 +13,7 @@ use crate::{
         heap::{Core as _, Heap},
         indirect::IndirectTag,
         mu::Mu,
+        reader::Location,
         stream,
         types::{Tag, TagType, Type},
     },
@@ -106,6 +107,86 @@ impl Stream {
 
         heap_ref.write_image(slices, offset);
     }
+
+    // input streams track the location of their next character
+    pub fn location(mu: &Mu, stream: Tag) -> Option<Location> {
+        let image = Self::to_image(mu, stream);
+
+        if image.direction.eq_(&Symbol::keyword("output")) {
+            return None;
+        }
+
+        match image.stream_id.type_of() {
+            Type::Fixnum => {
+                let index_ref = mu.stream_index.borrow();
+
+                match index_ref.get(&(Fixnum::as_i64(image.stream_id) as usize)) {
+                    Some((location, _)) => Some(location.clone()),
+                    None => Some(Location {
+                        path: None,
+                        line: 1,
+                        column: 1,
+                    }),
+                }
+            }
+            _ => None,
+        }
+    }
+
+    pub fn set_path(mu: &Mu, stream_id: usize, path: &str) {
+        let mut index_ref = mu.stream_index.borrow_mut();
+
+        index_ref.insert(
+            stream_id,
+            (
+                Location {
+                    path: Some(path.to_string()),
+                    line: 1,
+                    column: 1,
+                },
+                1,
+            ),
+        );
+    }
+
+    // if the last character read was a newline, the column it ended
+    // is kept for unread_char, otherwise 0
+    fn advance(mu: &Mu, stream_id: usize, ch: char) {
+        let mut index_ref = mu.stream_index.borrow_mut();
+        let (location, column) = index_ref.entry(stream_id).or_insert_with(|| {
+            (
+                Location {
+                    path: None,
+                    line: 1,
+                    column: 1,
+                },
+                1,
+            )
+        });
+
+        if ch == '\n' {
+            *column = location.column;
+            location.line += 1;
+            location.column = 1
+        } else {
+            *column = 0;
+            location.column += 1
+        }
+    }
+
+    fn retreat(mu: &Mu, stream_id: usize) {
+        let mut index_ref = mu.stream_index.borrow_mut();
+
+        if let Some((location, column)) = index_ref.get_mut(&stream_id) {
+            if *column == 0 {
+                location.column -= 1
+            } else {
+                location.line -= 1;
+                location.column = *column;
+                *column = 0
+            }
+        }
+    }
 }
 
 pub trait Core {
@@ -182,6 +263,9 @@ impl Core for Stream {
         let mut image = Self::to_image(mu, stream);
 
         System::close(&mu.system, Fixnum::as_i64(image.stream_id) as usize).unwrap();
+        mu.stream_index
+            .borrow_mut()
+            .remove(&(Fixnum::as_i64(image.stream_id) as usize));
 
         image.stream_id = Symbol::keyword("t");
         Self::update(mu, &image, stream);
@@ -246,7 +330,10 @@ impl Core for Stream {
                 if unch.null_() {
                     match System::read_byte(&mu.system, stream_id) {
                         Ok(opt) => match opt {
-                            Some(byte) => Ok(Some(byte as char)),
+                            Some(byte) => {
+                                Self::advance(mu, stream_id, byte as char);
+                                Ok(Some(byte as char))
+                            }
                             None => {
                                 image.eof = Symbol::keyword("t");
                                 Self::update(mu, &image, stream);
@@ -258,6 +345,7 @@ impl Core for Stream {
                 } else {
                     image.unch = Tag::nil();
                     Self::update(mu, &image, stream);
+                    Self::advance(mu, stream_id, Char::as_char(mu, unch));
 
                     Ok(Some(Char::as_char(mu, unch)))
                 }
@@ -324,6 +412,10 @@ impl Core for Stream {
             image.unch = Char::as_tag(ch);
             Self::update(mu, &image, stream);
 
+            if let Type::Fixnum = image.stream_id.type_of() {
+                Self::retreat(mu, Fixnum::as_i64(image.stream_id) as usize)
+            }
+
             Ok(None)
         } else {
             Err(Exception::new(
@@ -99,7 +99,10 @@ impl StreamBuilder {
         match &self.file {
             Some(path) => match self.input {
                 Some(input) => match System::open_input_file(&mu.system, path) {
-                    Ok(id) => Self::stream(id, input),
+                    Ok(id) => {
+                        Stream::set_path(mu, id, path);
+                        Self::stream(id, input)
+                    }
                     Err(e) => Err(e),
                 },
                 None => match self.output {
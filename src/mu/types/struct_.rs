@@ -4,19 +4,23 @@
 //! mu struct type
 use crate::{
     core::{
+        compiler::Compiler,
         exception::{self, Condition, Exception},
         frame::Frame,
         funcall::Core as _,
         heap::{Core as _, Heap},
         indirect::IndirectTag,
         mu::{Core as _, Mu},
+        namespace::Namespace,
         stream,
         types::{Tag, TagType, Type},
     },
     types::{
+        array::Array,
         cons::{Cons, ConsIter, Core as _},
+        fixnum::Fixnum,
         stream::{Core as _, Stream},
-        symbol::{Core as _, Symbol},
+        symbol::{Core as _, Symbol, UNBOUND},
         vecimage::{TypedVec, VecType, VectorIter},
         vector::{Core as _, Vector},
     },
@@ -30,6 +34,42 @@ pub struct Struct {
     pub vector: Tag,
 }
 
+// a registered struct type, parent slots come first
+pub struct StructType {
+    pub parent: Tag,
+    pub slots: Vec<(Tag, Type)>,
+}
+
+impl StructType {
+    fn is_type(slot_type: Type, value: Tag) -> bool {
+        match slot_type {
+            Type::T => true,
+            Type::List => matches!(value.type_of(), Type::Cons | Type::Null),
+            // bytes are fixnums, as in byte vectors
+            Type::Byte => {
+                value.type_of() == Type::Fixnum && (0..=255).contains(&Fixnum::as_i64(value))
+            }
+            _ => value.type_of() == slot_type,
+        }
+    }
+
+    fn slots(mu: &Mu, stype: Tag) -> Option<Vec<(Tag, Type)>> {
+        let struct_ref = block_on(mu.struct_index.read());
+
+        struct_ref
+            .get(&stype.as_u64())
+            .map(|struct_type| struct_type.slots.clone())
+    }
+
+    fn parent(mu: &Mu, stype: Tag) -> Option<Tag> {
+        let struct_ref = block_on(mu.struct_index.read());
+
+        struct_ref
+            .get(&stype.as_u64())
+            .map(|struct_type| struct_type.parent)
+    }
+}
+
 impl Struct {
     pub fn to_image(mu: &Mu, tag: Tag) -> Self {
         match tag.type_of() {
@@ -62,6 +102,27 @@ impl Struct {
         Self::to_image(mu, tag).vector
     }
 
+    // check a struct's contents against its registered type, if any
+    pub fn validate(mu: &Mu, stype: Tag, vec: &[Tag], fn_name: &str) -> exception::Result<()> {
+        match StructType::slots(mu, stype) {
+            Some(slots) => {
+                if slots.len() != vec.len() {
+                    return Err(Exception::new(Condition::Arity, fn_name, stype));
+                }
+
+                match slots
+                    .iter()
+                    .zip(vec.iter())
+                    .find(|((_, slot_type), value)| !StructType::is_type(*slot_type, **value))
+                {
+                    Some((_, value)) => Err(Exception::new(Condition::Type, fn_name, *value)),
+                    None => Ok(()),
+                }
+            }
+            None => Ok(()),
+        }
+    }
+
     pub fn to_tag(mu: &Mu, stype: Tag, vec: Vec<Tag>) -> Tag {
         match stype.type_of() {
             Type::Keyword => {
@@ -111,20 +172,32 @@ impl<'a> Core<'a> for Struct {
         std::mem::size_of::<Struct>() + Vector::heap_size(mu, Self::vector(mu, r#struct))
     }
 
-    fn write(mu: &Mu, tag: Tag, _: bool, stream: Tag) -> exception::Result<()> {
+    fn write(mu: &Mu, tag: Tag, escape: bool, stream: Tag) -> exception::Result<()> {
+        if Array::is_array(mu, tag) {
+            return Array::write(mu, tag, escape, stream);
+        }
+
         match tag {
             Tag::Indirect(_) => {
+                let image = Self::to_image(mu, tag);
+                let vec = VectorIter::new(mu, image.vector).collect::<Vec<Tag>>();
+
+                match Self::validate(mu, image.stype, &vec, "write") {
+                    Ok(_) => (),
+                    Err(e) => return Err(e),
+                }
+
                 match <Mu as stream::Core>::write_string(mu, "#s(", stream) {
                     Ok(_) => (),
                     Err(e) => return Err(e),
                 }
 
-                match <Mu as stream::Core>::write(mu, Self::to_image(mu, tag).stype, true, stream) {
+                match <Mu as stream::Core>::write(mu, image.stype, true, stream) {
                     Ok(_) => (),
                     Err(e) => return Err(e),
                 }
 
-                for tag in VectorIter::new(mu, Self::to_image(mu, tag).vector) {
+                for tag in vec {
                     match <Mu as stream::Core>::write_string(mu, " ", stream) {
                         Ok(_) => (),
                         Err(e) => return Err(e),
@@ -166,7 +239,10 @@ impl<'a> Core<'a> for Struct {
                                 vec.push(Cons::car(mu, cons));
                             }
 
-                            Ok(Self::to_tag(mu, stype, vec))
+                            match Self::validate(mu, stype, &vec, "read:st") {
+                                Ok(_) => Ok(Self::to_tag(mu, stype, vec)),
+                                Err(e) => Err(e),
+                            }
                         }
                         _ => Err(Exception::new(Condition::Type, "read:st", stype)),
                     }
@@ -196,6 +272,11 @@ pub trait MuFunction {
     fn mu_struct_type(_: &Mu, _: &mut Frame) -> exception::Result<()>;
     fn mu_struct_vector(_: &Mu, _: &mut Frame) -> exception::Result<()>;
     fn mu_make_struct(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+    fn mu_struct_def(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+    fn mu_struct_isa(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+    fn mu_struct_ref(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+    fn mu_struct_desc(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+    fn mu_struct_gen(_: &Mu, _: &mut Frame) -> exception::Result<()>;
 }
 
 impl MuFunction for Struct {
@@ -232,9 +313,220 @@ impl MuFunction for Struct {
                     vec.push(Cons::car(mu, cons));
                 }
 
-                let vector = TypedVec::<Vec<Tag>> { vec }.vec.to_vector().evict(mu);
+                match Self::validate(mu, stype, &vec, "struct") {
+                    Ok(_) => {
+                        let vector = TypedVec::<Vec<Tag>> { vec }.vec.to_vector().evict(mu);
 
-                Struct { stype, vector }.evict(mu)
+                        Struct { stype, vector }.evict(mu)
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            Err(e) => return Err(e),
+        };
+
+        Ok(())
+    }
+
+    fn mu_struct_def(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        let stype = fp.argv[0];
+        let slot_list = fp.argv[1];
+        let parent = fp.argv[2];
+
+        fp.value = match mu.fp_argv_check("st-def", &[Type::Keyword, Type::List, Type::T], fp) {
+            Ok(_) => {
+                if StructType::slots(mu, stype).is_some() {
+                    return Err(Exception::new(Condition::Error, "st-def", stype));
+                }
+
+                let mut slots = if parent.null_() {
+                    Vec::new()
+                } else {
+                    match StructType::slots(mu, parent) {
+                        Some(slots) => slots,
+                        None => return Err(Exception::new(Condition::Type, "st-def", parent)),
+                    }
+                };
+
+                for cons in ConsIter::new(mu, slot_list) {
+                    let slot = Cons::car(mu, cons);
+
+                    let (name, slot_type) = match slot.type_of() {
+                        Type::Keyword => (slot, Type::T),
+                        Type::Cons => match Tag::key_type(Cons::cdr(mu, slot)) {
+                            Some(slot_type) => (Cons::car(mu, slot), slot_type),
+                            None => {
+                                return Err(Exception::new(
+                                    Condition::Type,
+                                    "st-def",
+                                    Cons::cdr(mu, slot),
+                                ))
+                            }
+                        },
+                        _ => return Err(Exception::new(Condition::Type, "st-def", slot)),
+                    };
+
+                    if name.type_of() != Type::Keyword
+                        || slots.iter().any(|(slot_name, _)| slot_name.eq_(&name))
+                    {
+                        return Err(Exception::new(Condition::Type, "st-def", name));
+                    }
+
+                    slots.push((name, slot_type))
+                }
+
+                let mut struct_ref = block_on(mu.struct_index.write());
+
+                struct_ref.insert(stype.as_u64(), StructType { parent, slots });
+
+                stype
+            }
+            Err(e) => return Err(e),
+        };
+
+        Ok(())
+    }
+
+    fn mu_struct_isa(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        let tag = fp.argv[0];
+        let stype = fp.argv[1];
+
+        fp.value = match mu.fp_argv_check("st-isa", &[Type::T, Type::Keyword], fp) {
+            Ok(_) => match tag.type_of() {
+                Type::Struct => {
+                    let mut type_ = Self::stype(mu, tag);
+
+                    loop {
+                        if type_.eq_(&stype) {
+                            break Symbol::keyword("t");
+                        }
+
+                        match StructType::parent(mu, type_) {
+                            Some(parent) if !parent.null_() => type_ = parent,
+                            _ => break Tag::nil(),
+                        }
+                    }
+                }
+                _ => Tag::nil(),
+            },
+            Err(e) => return Err(e),
+        };
+
+        Ok(())
+    }
+
+    fn mu_struct_ref(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        let tag = fp.argv[0];
+        let slot = fp.argv[1];
+
+        fp.value = match mu.fp_argv_check("st-ref", &[Type::Struct, Type::Keyword], fp) {
+            Ok(_) => match StructType::slots(mu, Self::stype(mu, tag)) {
+                Some(slots) => match slots.iter().position(|(name, _)| name.eq_(&slot)) {
+                    Some(index) => Vector::r#ref(mu, Self::vector(mu, tag), index).unwrap(),
+                    None => return Err(Exception::new(Condition::Range, "st-ref", slot)),
+                },
+                None => return Err(Exception::new(Condition::Type, "st-ref", tag)),
+            },
+            Err(e) => return Err(e),
+        };
+
+        Ok(())
+    }
+
+    fn mu_struct_desc(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        let stype = fp.argv[0];
+
+        fp.value = match mu.fp_argv_check("st-desc", &[Type::Keyword], fp) {
+            Ok(_) => match StructType::slots(mu, stype) {
+                Some(slots) => Cons::vlist(
+                    mu,
+                    &slots
+                        .iter()
+                        .map(|(name, slot_type)| match Tag::type_key(*slot_type) {
+                            Some(type_key) => Cons::new(*name, type_key).evict(mu),
+                            None => panic!(),
+                        })
+                        .collect::<Vec<Tag>>(),
+                ),
+                None => return Err(Exception::new(Condition::Type, "st-desc", stype)),
+            },
+            Err(e) => return Err(e),
+        };
+
+        Ok(())
+    }
+
+    // (mu:st-gen type ns) interns make-<type>, <type>-<slot> and
+    // <type>-p in ns, returns the list of interned symbols
+    fn mu_struct_gen(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        let stype = fp.argv[0];
+        let ns = fp.argv[1];
+
+        fp.value = match mu.fp_argv_check("st-gen", &[Type::Keyword, Type::T], fp) {
+            Ok(_) => {
+                let slots = match StructType::slots(mu, stype) {
+                    Some(slots) => slots,
+                    None => return Err(Exception::new(Condition::Type, "st-gen", stype)),
+                };
+
+                if Namespace::is_ns(mu, ns).is_none() {
+                    return Err(Exception::new(Condition::Type, "st-gen", ns));
+                }
+
+                let mu_fn =
+                    |name| Symbol::value(mu, Namespace::map_symbol(mu, mu.mu_ns, name).unwrap());
+                let lambda = |args: &[Tag], body: &[Tag]| {
+                    Cons::vlist(
+                        mu,
+                        &[
+                            Symbol::keyword("lambda"),
+                            Cons::vlist(mu, args),
+                            Cons::vlist(mu, body),
+                        ],
+                    )
+                };
+                let name = |symbol| Vector::as_string(mu, Symbol::name(mu, symbol));
+                let type_name = name(stype);
+
+                // (:lambda (slot...) (mu:struct type (mu:cons slot ...)))
+                let args = slots
+                    .iter()
+                    .map(|(slot, _)| Symbol::new(mu, Tag::nil(), &name(*slot), *UNBOUND).evict(mu))
+                    .collect::<Vec<Tag>>();
+                let list = args.iter().rev().fold(Tag::nil(), |list, arg| {
+                    Cons::vlist(mu, &[mu_fn("cons"), *arg, list])
+                });
+
+                let mut fns = vec![(
+                    format!("make-{type_name}"),
+                    lambda(&args, &[mu_fn("struct"), stype, list]),
+                )];
+
+                // (:lambda (struct) (mu:st-ref struct slot))
+                let arg = Symbol::new(mu, Tag::nil(), "struct", *UNBOUND).evict(mu);
+                for (slot, _) in &slots {
+                    fns.push((
+                        format!("{type_name}-{}", name(*slot)),
+                        lambda(&[arg], &[mu_fn("st-ref"), arg, *slot]),
+                    ))
+                }
+
+                // (:lambda (obj) (mu:st-isa obj type))
+                let arg = Symbol::new(mu, Tag::nil(), "obj", *UNBOUND).evict(mu);
+                fns.push((
+                    format!("{type_name}-p"),
+                    lambda(&[arg], &[mu_fn("st-isa"), arg, stype]),
+                ));
+
+                let mut symbols = Vec::new();
+                for (fn_name, form) in fns {
+                    match Compiler::compile(mu, form) {
+                        Ok(func) => symbols.push(Namespace::intern_symbol(mu, ns, fn_name, func)),
+                        Err(e) => return Err(e),
+                    }
+                }
+
+                Cons::vlist(mu, &symbols)
             }
             Err(e) => return Err(e),
         };
@@ -19,6 +19,7 @@ use {
         },
         types::{
             char::Char,
+            cons::{Cons, Core as _},
             stream::{Core as _, Stream},
             vecimage::{TypedVec, VecType},
             vector::{Core as _, Vector},
@@ -38,6 +39,8 @@ pub struct SymbolImage {
     pub namespace: Tag,
     pub name: Tag,
     pub value: Tag,
+    pub plist: Tag,
+    pub doc: Tag,
 }
 
 lazy_static! {
@@ -55,6 +58,8 @@ impl Symbol {
                 namespace,
                 name: Vector::from_string(name).evict(mu),
                 value,
+                plist: Tag::nil(),
+                doc: Tag::nil(),
             }),
         }
     }
@@ -78,6 +83,16 @@ impl Symbol {
                             .image_slice(main.image_id() as usize + 16, 8)
                             .unwrap(),
                     ),
+                    plist: Tag::from_slice(
+                        heap_ref
+                            .image_slice(main.image_id() as usize + 24, 8)
+                            .unwrap(),
+                    ),
+                    doc: Tag::from_slice(
+                        heap_ref
+                            .image_slice(main.image_id() as usize + 32, 8)
+                            .unwrap(),
+                    ),
                 },
                 _ => panic!(),
             },
@@ -116,6 +131,60 @@ impl Symbol {
             _ => panic!(),
         }
     }
+
+    // uninterned symbols have a null namespace and aren't
+    // found in the null namespace under their name
+    pub fn is_uninterned(mu: &Mu, symbol: Tag) -> bool {
+        match symbol.type_of() {
+            Type::Symbol => {
+                Self::namespace(mu, symbol).null_()
+                    && match Namespace::map_symbol(
+                        mu,
+                        mu.null_ns,
+                        &Vector::as_string(mu, Self::name(mu, symbol)),
+                    ) {
+                        Some(interned) => !interned.eq_(&symbol),
+                        None => true,
+                    }
+            }
+            _ => false,
+        }
+    }
+
+    pub fn plist(mu: &Mu, symbol: Tag) -> Tag {
+        match symbol.type_of() {
+            Type::Null | Type::Keyword => Tag::nil(),
+            Type::Symbol => Self::to_image(mu, symbol).plist,
+            _ => panic!(),
+        }
+    }
+
+    pub fn doc(mu: &Mu, symbol: Tag) -> Tag {
+        match symbol.type_of() {
+            Type::Null | Type::Keyword => Tag::nil(),
+            Type::Symbol => Self::to_image(mu, symbol).doc,
+            _ => panic!(),
+        }
+    }
+
+    fn update(mu: &Mu, image: &SymbolImage, symbol: Tag) {
+        let slices: &[[u8; 8]] = &[
+            image.namespace.as_slice(),
+            image.name.as_slice(),
+            image.value.as_slice(),
+            image.plist.as_slice(),
+            image.doc.as_slice(),
+        ];
+
+        let offset = match symbol {
+            Tag::Indirect(heap) => heap.image_id(),
+            _ => panic!(),
+        } as usize;
+
+        let mut heap_ref = block_on(mu.heap.write());
+
+        heap_ref.write_image(slices, offset);
+    }
 }
 
 pub trait Core {
@@ -147,10 +216,14 @@ impl Core for Symbol {
     fn heap_size(mu: &Mu, symbol: Tag) -> usize {
         let name_sz = Heap::heap_size(mu, Self::name(mu, symbol));
         let value_sz = Heap::heap_size(mu, Self::value(mu, symbol));
+        let plist_sz = Heap::heap_size(mu, Self::plist(mu, symbol));
+        let doc_sz = Heap::heap_size(mu, Self::doc(mu, symbol));
 
         std::mem::size_of::<Symbol>()
             + if name_sz > 8 { name_sz } else { 0 }
             + if value_sz > 8 { value_sz } else { 0 }
+            + if plist_sz > 8 { plist_sz } else { 0 }
+            + if doc_sz > 8 { doc_sz } else { 0 }
     }
 
     fn gc_mark(mu: &Mu, symbol: Tag) {
@@ -162,6 +235,8 @@ impl Core for Symbol {
                 if !mark {
                     Mu::gc_mark(mu, Self::name(mu, symbol));
                     Mu::gc_mark(mu, Self::value(mu, symbol));
+                    Mu::gc_mark(mu, Self::plist(mu, symbol));
+                    Mu::gc_mark(mu, Self::doc(mu, symbol));
                 }
             }
         }
@@ -175,6 +250,8 @@ impl Core for Symbol {
                     image.namespace.as_slice(),
                     image.name.as_slice(),
                     image.value.as_slice(),
+                    image.plist.as_slice(),
+                    image.doc.as_slice(),
                 ];
 
                 let mut heap_ref = block_on(mu.heap.write());
@@ -275,7 +352,12 @@ impl Core for Symbol {
             Type::Symbol => {
                 let name = Self::name(mu, symbol);
 
-                if escape {
+                if escape && Self::is_uninterned(mu, symbol) {
+                    match <Mu as stream::Core>::write_string(mu, "#:", stream) {
+                        Ok(_) => (),
+                        Err(e) => return Err(e),
+                    }
+                } else if escape {
                     let ns = Self::namespace(mu, symbol);
 
                     if !Tag::null_(&ns) && !mu.null_ns.eq_(&ns) {
@@ -308,6 +390,12 @@ pub trait MuFunction {
     fn mu_boundp(_: &Mu, _: &mut Frame) -> exception::Result<()>;
     fn mu_symbol(_: &Mu, _: &mut Frame) -> exception::Result<()>;
     fn mu_keyword(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+    fn mu_gensym(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+    fn mu_plist(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+    fn mu_get(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+    fn mu_put(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+    fn mu_doc(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+    fn mu_set_doc(_: &Mu, _: &mut Frame) -> exception::Result<()>;
 }
 
 impl MuFunction for Symbol {
@@ -400,6 +488,116 @@ impl MuFunction for Symbol {
 
         Ok(())
     }
+
+    fn mu_gensym(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        let mut gensym_ref = block_on(mu.gensym_id.write());
+
+        fp.value = Self::new(mu, Tag::nil(), &format!("g{:X}", *gensym_ref), *UNBOUND).evict(mu);
+        *gensym_ref += 1;
+
+        Ok(())
+    }
+
+    fn mu_plist(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        let symbol = fp.argv[0];
+
+        fp.value = match symbol.type_of() {
+            Type::Null | Type::Keyword | Type::Symbol => Self::plist(mu, symbol),
+            _ => return Err(Exception::new(Condition::Type, "plist", symbol)),
+        };
+
+        Ok(())
+    }
+
+    fn mu_get(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        let symbol = fp.argv[0];
+        let key = fp.argv[1];
+
+        fp.value = match symbol.type_of() {
+            Type::Null | Type::Keyword | Type::Symbol => {
+                let mut value = Tag::nil();
+                let mut plist = Self::plist(mu, symbol);
+
+                while !plist.null_() {
+                    if Cons::car(mu, plist).eq_(&key) {
+                        value = Cons::car(mu, Cons::cdr(mu, plist));
+                        break;
+                    }
+
+                    plist = Cons::cdr(mu, Cons::cdr(mu, plist))
+                }
+
+                value
+            }
+            _ => return Err(Exception::new(Condition::Type, "sy-get", symbol)),
+        };
+
+        Ok(())
+    }
+
+    fn mu_put(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        let symbol = fp.argv[0];
+        let key = fp.argv[1];
+        let value = fp.argv[2];
+
+        fp.value = match mu.fp_argv_check("sy-put", &[Type::Symbol, Type::T, Type::T], fp) {
+            Ok(_) => {
+                let mut image = Self::to_image(mu, symbol);
+                let mut props = vec![key, value];
+                let mut plist = image.plist;
+
+                while !plist.null_() {
+                    let prop = Cons::car(mu, plist);
+
+                    plist = Cons::cdr(mu, plist);
+                    if !prop.eq_(&key) {
+                        props.push(prop);
+                        props.push(Cons::car(mu, plist));
+                    }
+
+                    plist = Cons::cdr(mu, plist)
+                }
+
+                image.plist = Cons::vlist(mu, &props);
+                Self::update(mu, &image, symbol);
+
+                value
+            }
+            Err(e) => return Err(e),
+        };
+
+        Ok(())
+    }
+
+    fn mu_doc(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        let symbol = fp.argv[0];
+
+        fp.value = match symbol.type_of() {
+            Type::Null | Type::Keyword | Type::Symbol => Self::doc(mu, symbol),
+            _ => return Err(Exception::new(Condition::Type, "sy-doc", symbol)),
+        };
+
+        Ok(())
+    }
+
+    fn mu_set_doc(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        let symbol = fp.argv[0];
+        let doc = fp.argv[1];
+
+        fp.value = match mu.fp_argv_check("sy-sdoc", &[Type::Symbol, Type::T], fp) {
+            Ok(_) => {
+                let mut image = Self::to_image(mu, symbol);
+
+                image.doc = doc;
+                Self::update(mu, &image, symbol);
+
+                doc
+            }
+            Err(e) => return Err(e),
+        };
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -48,6 +48,7 @@ pub trait IVector {
     fn image(_: &VectorImage) -> Vec<[u8; 8]>;
     fn evict(&self, _: &Mu) -> Tag;
     fn r#ref(_: &Mu, _: Tag, _: usize) -> Option<Tag>;
+    fn set(_: &Mu, _: Tag, _: usize, _: Tag) -> Option<Tag>;
 }
 
 impl<'a> IVector for IndirectVector<'a> {
@@ -248,6 +249,40 @@ impl<'a> IVector for IndirectVector<'a> {
             _ => panic!(),
         }
     }
+    fn set(mu: &Mu, vector: Tag, index: usize, value: Tag) -> Option<Tag> {
+        let image = Vector::to_image(mu, vector);
+
+        let len = Fixnum::as_i64(image.length) as usize;
+        if index >= len {
+            return None;
+        }
+
+        let offset = match vector {
+            Tag::Indirect(image) => image.image_id() as usize + Self::IMAGE_NBYTES,
+            _ => panic!(),
+        };
+
+        let (bytes, offset) = match Vector::to_type(image.vtype).unwrap() {
+            Type::Byte => (vec![Fixnum::as_i64(value) as u8], offset + index),
+            Type::Char => (vec![Char::as_char(mu, value) as u8], offset + index),
+            Type::T => (value.as_slice().to_vec(), offset + (index * 8)),
+            Type::Fixnum => (
+                Fixnum::as_i64(value).to_le_bytes().to_vec(),
+                offset + (index * 8),
+            ),
+            Type::Float => (
+                Float::as_f32(mu, value).to_le_bytes().to_vec(),
+                offset + (index * 4),
+            ),
+            _ => panic!(),
+        };
+
+        let mut heap_ref = block_on(mu.heap.write());
+
+        heap_ref.write_slice(&bytes, offset);
+
+        Some(value)
+    }
 }
 
 /// typed vector allocation
@@ -105,6 +105,58 @@ impl Vector {
             }
         }
     }
+
+    pub fn is_type(vtype: Type, value: Tag) -> bool {
+        match vtype {
+            Type::T => true,
+            Type::Char => value.type_of() == Type::Char,
+            Type::Byte | Type::Fixnum => value.type_of() == Type::Fixnum,
+            Type::Float => value.type_of() == Type::Float,
+            _ => false,
+        }
+    }
+
+    // char vectors hold a byte per char, so only ascii chars fit
+    pub fn in_range(mu: &Mu, vtype: Type, value: Tag) -> bool {
+        match vtype {
+            Type::Char => value.data(mu) < 0x80,
+            Type::Byte => (0..=255).contains(&Fixnum::as_i64(value)),
+            _ => true,
+        }
+    }
+
+    pub fn copy(mu: &Mu, vector: Tag) -> Vector {
+        let image = VectorImage {
+            vtype: match Tag::type_key(Self::type_of(mu, vector)) {
+                Some(key) => key,
+                None => panic!(),
+            },
+            length: Fixnum::as_tag(Self::length(mu, vector) as i64),
+        };
+
+        let ivec = match Self::type_of(mu, vector) {
+            Type::T => IVec::T(VectorIter::new(mu, vector).collect()),
+            Type::Char => IVec::Char(
+                VectorIter::new(mu, vector)
+                    .map(|ch| Char::as_char(mu, ch))
+                    .collect(),
+            ),
+            Type::Byte => IVec::Byte(
+                VectorIter::new(mu, vector)
+                    .map(|byte| Fixnum::as_i64(byte) as u8)
+                    .collect(),
+            ),
+            Type::Fixnum => IVec::Fixnum(VectorIter::new(mu, vector).map(Fixnum::as_i64).collect()),
+            Type::Float => IVec::Float(
+                VectorIter::new(mu, vector)
+                    .map(|fl| Float::as_f32(mu, fl))
+                    .collect(),
+            ),
+            _ => panic!(),
+        };
+
+        Vector::Indirect((image, ivec))
+    }
 }
 
 // core
@@ -116,6 +168,7 @@ pub trait Core<'a> {
     fn heap_size(_: &Mu, _: Tag) -> usize;
     fn read(_: &Mu, _: char, _: Tag) -> exception::Result<Tag>;
     fn r#ref(_: &Mu, _: Tag, _: usize) -> Option<Tag>;
+    fn set(_: &Mu, _: Tag, _: usize, _: Tag) -> Option<Tag>;
     fn view(_: &Mu, _: Tag) -> Tag;
     fn write(_: &Mu, _: Tag, _: bool, _: Tag) -> exception::Result<()>;
 }
@@ -460,6 +513,17 @@ impl<'a> Core<'a> for Vector {
             _ => panic!(),
         }
     }
+
+    // direct vectors are immediate, they have no storage to update
+    fn set(mu: &Mu, vector: Tag, index: usize, value: Tag) -> Option<Tag> {
+        match vector.type_of() {
+            Type::Vector => match vector {
+                Tag::Direct(_) => None,
+                Tag::Indirect(_) => IndirectVector::set(mu, vector, index, value),
+            },
+            _ => panic!(),
+        }
+    }
 }
 
 /// mu functions
@@ -468,6 +532,9 @@ pub trait MuFunction {
     fn mu_length(_: &Mu, _: &mut Frame) -> exception::Result<()>;
     fn mu_make_vector(_: &Mu, _: &mut Frame) -> exception::Result<()>;
     fn mu_svref(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+    fn mu_svset(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+    fn mu_svfill(_: &Mu, _: &mut Frame) -> exception::Result<()>;
+    fn mu_svcopy(_: &Mu, _: &mut Frame) -> exception::Result<()>;
 }
 
 impl MuFunction for Vector {
@@ -589,6 +656,85 @@ impl MuFunction for Vector {
         Ok(())
     }
 
+    fn mu_svset(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        let vector = fp.argv[0];
+        let index = fp.argv[1];
+        let value = fp.argv[2];
+
+        fp.value = match mu.fp_argv_check("sv-set", &[Type::Vector, Type::Fixnum, Type::T], fp) {
+            Ok(_) => {
+                let nth = Fixnum::as_i64(index);
+
+                if nth < 0 || nth as usize >= Self::length(mu, vector) {
+                    return Err(Exception::new(Condition::Range, "sv-set", index));
+                }
+
+                // direct vectors are immutable, sv-copy makes an updatable one
+                if let Tag::Direct(_) = vector {
+                    return Err(Exception::new(Condition::Type, "sv-set", vector));
+                }
+
+                if !Self::is_type(Self::type_of(mu, vector), value) {
+                    return Err(Exception::new(Condition::Type, "sv-set", value));
+                }
+
+                if !Self::in_range(mu, Self::type_of(mu, vector), value) {
+                    return Err(Exception::new(Condition::Range, "sv-set", value));
+                }
+
+                match Self::set(mu, vector, nth as usize, value) {
+                    Some(value) => value,
+                    None => panic!(),
+                }
+            }
+            Err(e) => return Err(e),
+        };
+
+        Ok(())
+    }
+
+    fn mu_svfill(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        let vector = fp.argv[0];
+        let value = fp.argv[1];
+
+        fp.value = match mu.fp_argv_check("sv-fill", &[Type::Vector, Type::T], fp) {
+            Ok(_) => {
+                // direct vectors are immutable, sv-copy makes an updatable one
+                if let Tag::Direct(_) = vector {
+                    return Err(Exception::new(Condition::Type, "sv-fill", vector));
+                }
+
+                if !Self::is_type(Self::type_of(mu, vector), value) {
+                    return Err(Exception::new(Condition::Type, "sv-fill", value));
+                }
+
+                if !Self::in_range(mu, Self::type_of(mu, vector), value) {
+                    return Err(Exception::new(Condition::Range, "sv-fill", value));
+                }
+
+                for index in 0..Self::length(mu, vector) {
+                    Self::set(mu, vector, index, value);
+                }
+
+                vector
+            }
+            Err(e) => return Err(e),
+        };
+
+        Ok(())
+    }
+
+    fn mu_svcopy(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
+        let vector = fp.argv[0];
+
+        fp.value = match mu.fp_argv_check("sv-copy", &[Type::Vector], fp) {
+            Ok(_) => Self::copy(mu, vector).evict(mu),
+            Err(e) => return Err(e),
+        };
+
+        Ok(())
+    }
+
     fn mu_type(mu: &Mu, fp: &mut Frame) -> exception::Result<()> {
         let vector = fp.argv[0];
 
@@ -6,6 +6,8 @@ extern crate mu;
 
 use mu::{Condition, System};
 
+// the listener evaluates on its caller's stack, run it under
+// System::with_stack as ServerConfig does its -e and -l options
 pub fn _listener(system: &System, _config: &str) {
     let mu = system.mu();
 
@@ -29,38 +31,16 @@ pub fn _listener(system: &System, _config: &str) {
                             mu.write(eval, true, eval_string).unwrap();
                             println!("{}", mu.get_string(eval_string).unwrap());
                         }
-                        Err(e) => {
-                            eprint!(
-                                "eval exception raised by {}, {:?} condition on ",
-                                system.write(e.source, true),
-                                e.condition
-                            );
-                            mu.write(e.object, true, mu.err_out()).unwrap();
-                            eprintln!()
-                        }
+                        Err(e) => eprintln!("eval {}", system.error(e)),
                     },
-                    Err(e) => {
-                        eprint!(
-                            "compile exception raised by {}, {:?} condition on ",
-                            system.write(e.source, true),
-                            e.condition
-                        );
-                        mu.write(e.object, true, mu.err_out()).unwrap();
-                        eprintln!()
-                    }
+                    Err(e) => eprintln!("compile {}", system.error(e)),
                 }
             }
             Err(e) => {
                 if let Condition::Eof = e.condition {
                     std::process::exit(0);
                 } else {
-                    eprint!(
-                        "reader exception raised by {}, {:?} condition on ",
-                        system.write(e.source, true),
-                        e.condition
-                    );
-                    mu.write(e.object, true, mu.err_out()).unwrap();
-                    eprintln!()
+                    eprintln!("reader {}", system.error(e));
                 }
             }
         }
@@ -7,7 +7,7 @@ extern crate mu;
 use {
     futures::executor::block_on,
     getopt::Opt,
-    mu::{Mu, System},
+    mu::{Config, Mu, System},
     std::net::{SocketAddr, ToSocketAddrs},
 };
 
@@ -103,6 +103,31 @@ impl ServerConfig {
         Some(optv)
     }
 
+    // the -e and -l options, in order
+    fn eval_forms(config: &Config, forms: Vec<OptDef>) {
+        let system = System::new(config);
+
+        for opt in forms {
+            match opt.0 {
+                OptType::Eval => match system.eval(&opt.1) {
+                    Ok(_) => (),
+                    Err(e) => {
+                        eprintln!("runtime: error {}, {}", opt.1, system.error(e));
+                        std::process::exit(-1);
+                    }
+                },
+                OptType::Load => match system.load(&opt.1) {
+                    Ok(_) => (),
+                    Err(e) => {
+                        eprintln!("runtime: failed to load {}, {}", &opt.1, system.error(e));
+                        std::process::exit(-1);
+                    }
+                },
+                _ => (),
+            }
+        }
+    }
+
     pub fn new() -> Self {
         // 49152 to 65535 are dynamically available
         const SERVER_PORT: u16 = 50000;
@@ -120,39 +145,27 @@ impl ServerConfig {
                     }
                 }
 
-                let system = match System::config(&config) {
-                    Some(config) => System::new(&config),
+                let config = match System::config(&config) {
+                    Some(config) => config,
                     None => {
                         eprintln!("server: config error {}", config);
                         std::process::exit(-1)
                     }
                 };
 
+                let mut forms = Vec::new();
+
                 for opt in opts {
                     match opt.0 {
                         OptType::Config => (),
                         OptType::Ping => ping = true,
                         OptType::Socket => socket = opt.1.to_string(),
-                        OptType::Eval => match system.eval(&opt.1) {
-                            Ok(_) => (),
-                            Err(e) => {
-                                eprintln!("runtime: error {}, {}", opt.1, system.error(e));
-                                std::process::exit(-1);
-                            }
-                        },
-                        OptType::Load => match system.load(&opt.1) {
-                            Ok(_) => (),
-                            Err(e) => {
-                                eprintln!(
-                                    "runtime: failed to load {}, {}",
-                                    &opt.1,
-                                    system.error(e)
-                                );
-                                std::process::exit(-1);
-                            }
-                        },
+                        OptType::Eval | OptType::Load => forms.push(opt),
                     }
                 }
+
+                // evaluate on the configured stack
+                System::with_stack(&config, move || Self::eval_forms(&config, forms))
             }
             None => {
                 eprintln!("option: error");
@@ -14,7 +14,7 @@ extern crate mu;
 #[allow(unused_imports)]
 use {
     getopt::Opt,
-    mu::{Condition, Mu, Result, System, Tag},
+    mu::{Condition, Config, Mu, Result, System, Tag},
     std::{fs, io::Write},
 };
 
@@ -119,38 +119,16 @@ fn listener(system: &System) {
                             mu.write(eval, true, eval_string).unwrap();
                             println!("{}", mu.get_string(eval_string).unwrap());
                         }
-                        Err(e) => {
-                            eprint!(
-                                "eval exception raised by {}, {:?} condition on ",
-                                system.write(e.source, true),
-                                e.condition
-                            );
-                            mu.write(e.object, true, mu.err_out()).unwrap();
-                            eprintln!()
-                        }
+                        Err(e) => eprintln!("eval {}", system.error(e)),
                     },
-                    Err(e) => {
-                        eprint!(
-                            "compile exception raised by {}, {:?} condition on ",
-                            system.write(e.source, true),
-                            e.condition
-                        );
-                        mu.write(e.object, true, mu.err_out()).unwrap();
-                        eprintln!()
-                    }
+                    Err(e) => eprintln!("compile {}", system.error(e)),
                 }
             }
             Err(e) => {
                 if let Condition::Eof = e.condition {
                     std::process::exit(0);
                 } else {
-                    eprint!(
-                        "reader exception raised by {}, {:?} condition on ",
-                        system.write(e.source, true),
-                        e.condition
-                    );
-                    mu.write(e.object, true, mu.err_out()).unwrap();
-                    eprintln!()
+                    eprintln!("reader {}", system.error(e));
                 }
             }
         }
@@ -160,7 +138,6 @@ fn listener(system: &System) {
 pub fn main() {
     let mut _config = String::new();
     let mut _debug = false;
-    let mut pipe = false;
 
     match options(std::env::args().collect()) {
         Some(opts) => {
@@ -176,13 +153,18 @@ pub fn main() {
         }
     }
 
-    let system = match System::config(&_config) {
-        Some(config) => System::new(&config),
+    match System::config(&_config) {
+        Some(config) => System::with_stack(&config, move || run(&config)),
         None => {
             eprintln!("option: configuration error");
             std::process::exit(-1)
         }
-    };
+    }
+}
+
+fn run(config: &Config) {
+    let mut pipe = false;
+    let system = System::new(config);
 
     match options(std::env::args().collect()) {
         Some(opts) => {